@@ -0,0 +1,181 @@
+use super::Local;
+use crate::dataset::bundle::{BundleManifest, MANIFEST_FILE};
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::utils;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use iterum_rust::vc::Dataset;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the file in a bundle which contains the version history of the dataset.
+const DATASET_FILE: &str = "dataset.json";
+
+impl Local {
+    fn get_dataset_dir(&self, dataset_path: &str) -> PathBuf {
+        PathBuf::from(format!("{}{}", self.path, dataset_path))
+    }
+
+    pub fn bundle_files(&self, dataset: &DatasetConfig, include_runs: bool) -> Result<Vec<String>, DaemonError> {
+        let dataset_dir = self.get_dataset_dir(&dataset.name);
+        Ok(utils::list_files_recursively(&dataset_dir)?
+            .into_iter()
+            .filter(|file| file != DATASET_FILE && (include_runs || !file.starts_with("runs/")))
+            .collect())
+    }
+
+    pub fn export_bundle(
+        &self,
+        dataset: &DatasetConfig,
+        vc_dataset: &Dataset,
+        files: &[String],
+        writer: &mut dyn Write,
+    ) -> Result<(), DaemonError> {
+        let dataset_dir = self.get_dataset_dir(&dataset.name);
+        debug!("Exporting {} files to bundle of dataset {}", files.len(), dataset.name);
+
+        let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+        let mut digests = BTreeMap::new();
+        let dataset_bytes = serde_json::to_vec_pretty(vc_dataset)?;
+        digests.insert(DATASET_FILE.to_owned(), format!("{:x}", Sha256::digest(&dataset_bytes)));
+        append_bytes(&mut builder, DATASET_FILE, &dataset_bytes)?;
+
+        // Files are read up to the length they had when they were opened, and digested while they are written, so files which grow during the export
+        // are consistent with the manifest. The manifest is written last, once all digests are known.
+        for file in files {
+            let source = match File::open(dataset_dir.join(file)) {
+                Ok(source) => source,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    debug!("File {} was removed before it was exported", file);
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+            let metadata = source.metadata()?;
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&metadata);
+            let mut reader = DigestReader::new(source.take(metadata.len()));
+            builder.append_data(&mut header, file, &mut reader)?;
+            if reader.length != metadata.len() {
+                return Err(DaemonError::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("File {} was truncated while it was exported.", file),
+                )));
+            }
+            digests.insert(file.to_string(), reader.digest());
+        }
+
+        let manifest_bytes = serde_json::to_vec_pretty(&BundleManifest::new(dataset, digests))?;
+        append_bytes(&mut builder, MANIFEST_FILE, &manifest_bytes)?;
+        builder.into_inner()?.finish()?;
+
+        Ok(())
+    }
+
+    pub fn import_bundle_files(
+        &self,
+        dataset_path: &str,
+        bundle_dir: &Path,
+        files: &[String],
+    ) -> Result<(), DaemonError> {
+        let dataset_dir = self.get_dataset_dir(dataset_path);
+        for file in files {
+            if !utils::is_contained_path(file) {
+                return Err(DaemonError::BadRequest(format!(
+                    "Invalid file name {} in bundle.",
+                    file
+                )));
+            }
+            debug!("Importing file {} from bundle", file);
+            utils::link_or_copy(&bundle_dir.join(file), &dataset_dir.join(file))?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends a file with the given contents to a tarball.
+fn append_bytes<W: Write>(builder: &mut tar::Builder<W>, name: &str, bytes: &[u8]) -> Result<(), DaemonError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, bytes)?;
+    Ok(())
+}
+
+/// Reader which computes the SHA-256 digest and the length of everything read through it.
+struct DigestReader<R> {
+    inner: R,
+    hasher: Sha256,
+    length: u64,
+}
+
+impl<R: Read> DigestReader<R> {
+    fn new(inner: R) -> DigestReader<R> {
+        DigestReader {
+            inner,
+            hasher: Sha256::new(),
+            length: 0,
+        }
+    }
+
+    fn digest(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for DigestReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.length += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Backend;
+    use flate2::read::GzDecoder;
+    use std::fs;
+
+    #[test]
+    fn exported_bundles_match_their_manifest() {
+        let root = std::env::temp_dir().join(format!("daemon-export-test-{}", std::process::id()));
+        let local = Local {
+            path: format!("{}/", root.display()),
+        };
+        let dataset = DatasetConfig {
+            name: "dataset".to_owned(),
+            backend: Backend::Local(local.clone()),
+            description: String::new(),
+            retention: None,
+        };
+        let data_dir = root.join("dataset/data/a.csv");
+        fs::create_dir_all(&data_dir).unwrap();
+        fs::write(data_dir.join("commit"), b"a,b\n1,2\n").unwrap();
+        fs::write(root.join("dataset/dataset.json"), b"{}").unwrap();
+
+        let files = local.bundle_files(&dataset, false).unwrap();
+        assert_eq!(files, vec!["data/a.csv/commit"]);
+        let mut bundle = Vec::new();
+        local
+            .export_bundle(&dataset, &Dataset::new(), &files, &mut bundle)
+            .unwrap();
+
+        let unpack_path = root.join("unpacked");
+        utils::unpack_without_links(GzDecoder::new(bundle.as_slice()), &unpack_path).unwrap();
+        let manifest: BundleManifest =
+            serde_json::from_str(&fs::read_to_string(unpack_path.join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert!(manifest.verify(&unpack_path).is_ok());
+        assert_eq!(manifest.files.len(), 2);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,10 +1,12 @@
 use super::Local;
 use crate::dataset::DatasetConfig;
 use crate::error::DaemonError;
+use crate::utils;
 use iterum_rust::vc::{Commit, Dataset};
 use std::fs;
 use std::fs::File;
 use std::io::Write;
+use std::path::{Path, PathBuf};
 
 impl Local {
     pub fn store_committed_files(
@@ -78,8 +80,55 @@ impl Local {
         Ok(dataset)
     }
 
+    pub fn fork_files(&self, source_path: &str, target_path: &str, files: &[String]) -> Result<(), DaemonError> {
+        for file in files {
+            let source_file = format!("{}{}/data/{}", self.path, source_path, file);
+            let target_file = format!("{}{}/data/{}", self.path, target_path, file);
+            debug!("Linking file {} to {}", source_file, target_file);
+            utils::link_or_copy(Path::new(&source_file), Path::new(&target_file))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_stored_files(&self, dataset_path: &str) -> Result<Vec<(String, u64)>, DaemonError> {
+        let data_path = PathBuf::from(format!("{}{}/data", self.path, dataset_path));
+        utils::list_files_recursively(&data_path)?
+            .into_iter()
+            .map(|file| -> Result<(String, u64), DaemonError> {
+                let size = fs::metadata(data_path.join(&file))?.len();
+                Ok((file, size))
+            })
+            .collect()
+    }
+
+    pub fn remove_stored_files(&self, dataset_path: &str, files: &[String]) -> Result<(), DaemonError> {
+        let data_path = PathBuf::from(format!("{}{}/data", self.path, dataset_path));
+        for file in files {
+            let file_path = data_path.join(file);
+            debug!("Removing file {:?}", file_path);
+            fs::remove_file(&file_path)?;
+            // Clean up the folder of the file as well, which only succeeds when it is empty.
+            if let Some(folder_path) = file_path.parent() {
+                if folder_path != data_path {
+                    let _ = fs::remove_dir(folder_path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rename_dataset(&self, dataset_path: &str, new_dataset_path: &str) -> Result<(), DaemonError> {
+        let path = format!("{}{}", self.path, dataset_path);
+        let new_path = format!("{}{}", self.path, new_dataset_path);
+        super::pipeline::forget_segment_indexes(Path::new(&path));
+        super::pipeline::forget_segment_indexes(Path::new(&new_path));
+        fs::rename(path, new_path)?;
+        Ok(())
+    }
+
     pub fn remove_dataset(&self, dataset_path: &str) -> Result<(), DaemonError> {
         let path = format!("{}{}", self.path, dataset_path);
+        super::pipeline::forget_segment_indexes(Path::new(&path));
         match fs::remove_dir_all(path) {
             Ok(()) => Ok(()),
             Err(_) => Ok(()),
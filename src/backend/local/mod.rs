@@ -1,5 +1,6 @@
 //! Contains logic regarding communication with the local storage backend.
 
+pub mod bundle;
 pub mod dataset;
 pub mod pipeline;
 use serde::{Deserialize, Serialize};
@@ -1,14 +1,21 @@
 use super::Local;
 use crate::dataset::DatasetConfig;
 use crate::error::DaemonError;
+use crate::pipeline::models::{PipelineStorageUsage, RemovalReport, ResultMetadata};
+use crate::utils;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use iterum_rust::pipeline::PipelineExecution;
 use iterum_rust::provenance::FragmentLineage;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::fs::File;
-use std::io::Write;
-
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 impl Local {
     fn get_pipelines_path(&self, dataset_name: &str) -> PathBuf {
@@ -24,10 +31,15 @@ impl Local {
 
     pub fn get_pipeline_executions(&self, dataset_path: &str) -> Result<Vec<String>, DaemonError> {
         let path = self.get_pipelines_path(dataset_path);
+        // Datasets without any pipeline executions do not have a runs folder yet.
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
 
-        let files: Vec<String> = fs::read_dir(path)?
-            .map(|direntry| direntry.unwrap().file_name().to_str().unwrap().into())
-            .collect();
+        let mut files: Vec<String> = Vec::new();
+        for direntry in fs::read_dir(path)? {
+            files.push(direntry?.file_name().to_string_lossy().into_owned());
+        }
 
         Ok(files)
     }
@@ -64,12 +76,68 @@ impl Local {
         Ok(())
     }
 
-    pub fn remove_pipeline_execution(&self, dataset: &DatasetConfig, pipeline_hash: &str) -> Result<(), DaemonError> {
+    pub fn remove_pipeline_execution(
+        &self,
+        dataset: &DatasetConfig,
+        pipeline_hash: &str,
+    ) -> Result<RemovalReport, DaemonError> {
         let path = self.get_pipeline_path(&dataset.name, &pipeline_hash);
-        match fs::remove_dir_all(path) {
-            Ok(()) => Ok(()),
-            Err(_) => Ok(()),
+        let files = utils::list_files_recursively(&path)?;
+        let bytes = utils::directory_size(&path)?;
+        fs::remove_dir_all(&path)?;
+        forget_segment_indexes(&path);
+
+        Ok(RemovalReport {
+            pipeline_hash: pipeline_hash.to_owned(),
+            files,
+            bytes,
+            overridden: Vec::new(),
+        })
+    }
+
+    pub fn store_pipeline_document<T: Serialize>(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+        name: &str,
+        document: &T,
+    ) -> Result<(), DaemonError> {
+        let path = self.get_pipeline_path(dataset_path, pipeline_hash);
+        if !path.exists() {
+            fs::create_dir_all(&path)?;
         }
+        let string = serde_json::to_string_pretty(document)?;
+        let mut file = File::create(path.join(format!("{}.json", name)))?;
+        file.write_all(&string.as_bytes())?;
+
+        Ok(())
+    }
+
+    pub fn get_pipeline_document<T: DeserializeOwned>(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+        name: &str,
+    ) -> Result<T, DaemonError> {
+        let path = self.get_pipeline_path(dataset_path, pipeline_hash);
+
+        let string = fs::read_to_string(path.join(format!("{}.json", name)))?;
+        let document: T = serde_json::from_str(&string)?;
+
+        Ok(document)
+    }
+
+    pub fn get_pipeline_storage_usage(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+    ) -> Result<PipelineStorageUsage, DaemonError> {
+        let path = self.get_pipeline_path(dataset_path, pipeline_hash);
+        Ok(PipelineStorageUsage {
+            pipeline_hash: pipeline_hash.to_owned(),
+            results_bytes: utils::directory_size(&path.join("results"))?,
+            lineage_bytes: utils::directory_size(&path.join("lineage"))?,
+        })
     }
 
     pub fn store_pipeline_result_files(
@@ -86,6 +154,9 @@ impl Local {
                 fs::create_dir_all(&path).expect("Could not create pipeline directory.");
             }
             let new_filepath = path.join(filename);
+            if let Some(parent) = new_filepath.parent() {
+                fs::create_dir_all(parent)?;
+            }
             fs::copy(&filepath, &new_filepath)?;
         }
 
@@ -94,11 +165,7 @@ impl Local {
 
     pub fn get_pipeline_results(&self, dataset_path: &str, pipeline_hash: &str) -> Result<Vec<String>, DaemonError> {
         let path = self.get_pipeline_path(dataset_path, &pipeline_hash).join("results");
-        let files: Vec<String> = fs::read_dir(path)?
-            .map(|direntry| direntry.unwrap().file_name().to_str().unwrap().into())
-            .collect();
-
-        Ok(files)
+        Ok(utils::list_files_recursively(&path)?)
     }
 
     pub fn get_pipeline_result(
@@ -119,20 +186,139 @@ impl Local {
         }
     }
 
+    pub fn copy_pipeline_result(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+        file_name: &str,
+        target: &Path,
+    ) -> Result<(), DaemonError> {
+        let path = self.get_pipeline_path(dataset_path, &pipeline_hash).join("results");
+        Ok(utils::link_or_copy(&path.join(file_name), target)?)
+    }
+
+    pub fn get_pipeline_result_metadata(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+        file_name: &str,
+    ) -> Result<ResultMetadata, DaemonError> {
+        let path = self.get_pipeline_path(dataset_path, &pipeline_hash).join("results");
+        Ok(ResultMetadata::from_file(&path.join(file_name), file_name)?)
+    }
+
+    pub fn export_pipeline_results(
+        &self,
+        dataset: &DatasetConfig,
+        pipeline_hash: &str,
+        include_provenance: bool,
+        writer: &mut dyn Write,
+    ) -> Result<(), DaemonError> {
+        let path = self.get_pipeline_path(&dataset.name, pipeline_hash);
+        let results_path = path.join("results");
+        let files = if results_path.exists() {
+            utils::list_files_recursively(&results_path)?
+        } else {
+            Vec::new()
+        };
+        debug!(
+            "Exporting {} results of pipeline execution {} to archive",
+            files.len(),
+            pipeline_hash
+        );
+
+        let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+        for file in &files {
+            builder.append_path_with_name(results_path.join(file), format!("results/{}", file))?;
+        }
+        if include_provenance {
+            let execution_path = path.join("execution.json");
+            if execution_path.exists() {
+                builder.append_path_with_name(execution_path, "execution.json")?;
+            }
+            // Lineage is written as a single document, independent of the format it is stored in.
+            let lineages = match self.get_all_pipeline_fragment_lineages(dataset, pipeline_hash) {
+                Ok(lineages) => lineages,
+                Err(DaemonError::NotFound) => Vec::new(),
+                Err(error) => return Err(error),
+            };
+            let lineage_bytes = serde_json::to_vec_pretty(&lineages)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(lineage_bytes.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, "lineage.json", lineage_bytes.as_slice())?;
+        }
+        builder.into_inner()?.finish()?;
+
+        Ok(())
+    }
+
+    fn get_lineage_path(&self, dataset_name: &str, pipeline_hash: &str) -> PathBuf {
+        self.get_pipeline_path(dataset_name, pipeline_hash).join("lineage")
+    }
+
     pub fn store_pipeline_fragment_lineage(
         &self,
         dataset: &DatasetConfig,
         pipeline_hash: &str,
         fragment: &FragmentLineage,
     ) -> Result<(), DaemonError> {
-        let path = self.get_pipeline_path(&dataset.name, &pipeline_hash).join("lineage");
-        if !path.exists() {
-            fs::create_dir_all(&path).expect("Could not create lineage directory.");
+        let path = self.get_lineage_path(&dataset.name, &pipeline_hash);
+        fs::create_dir_all(&path)?;
+
+        let mut record = serde_json::to_vec(fragment)?;
+        record.push(b'\n');
+
+        let segment_index = segment_index(&path);
+        let mut segment_index = segment_index.lock().unwrap();
+        segment_index.load(&path)?;
+
+        // An interrupted append may have left a partial record, or a record without an index entry, at the end of the
+        // segment. Both are dropped, such that the new record starts right after the last indexed one.
+        let offset = segment_index.segment_length;
+        let mut segment = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(path.join(LINEAGE_SEGMENT))?;
+        if segment.metadata()?.len() != offset {
+            warn!(
+                "Truncating lineage segment of {} to its last indexed record",
+                pipeline_hash
+            );
+            segment.set_len(offset)?;
         }
-        let new_filepath = path.join(&fragment.description.metadata.fragment_id);
-        let string = serde_json::to_string_pretty(fragment)?;
-        let mut file = File::create(new_filepath)?;
-        file.write_all(&string.as_bytes())?;
+        segment.seek(SeekFrom::Start(offset))?;
+        segment.write_all(&record)?;
+        // The record has to be durable before the index refers to it.
+        segment.sync_data()?;
+
+        let entry = LineageIndexEntry {
+            fragment_id: fragment.description.metadata.fragment_id.to_string(),
+            offset,
+            length: record.len() as u64,
+        };
+        let mut index_record = serde_json::to_vec(&entry)?;
+        index_record.push(b'\n');
+        let mut index = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(path.join(LINEAGE_INDEX))?;
+        if index.metadata()?.len() != segment_index.index_length {
+            warn!(
+                "Truncating partial entry at the end of the lineage index of {}",
+                pipeline_hash
+            );
+            index.set_len(segment_index.index_length)?;
+        }
+        index.seek(SeekFrom::Start(segment_index.index_length))?;
+        index.write_all(&index_record)?;
+        index.sync_data()?;
+        segment_index.index_length += index_record.len() as u64;
+        segment_index.segment_length = offset + entry.length;
+        segment_index
+            .locations
+            .insert(entry.fragment_id, (entry.offset, entry.length));
         Ok(())
     }
 
@@ -141,12 +327,17 @@ impl Local {
         dataset: &DatasetConfig,
         pipeline_hash: &str,
     ) -> Result<Vec<String>, DaemonError> {
-        let path = self.get_pipeline_path(&dataset.name, &pipeline_hash).join("lineage");
-
-        let fragments: Vec<String> = fs::read_dir(path)?
-            .map(|direntry| direntry.unwrap().file_name().to_str().unwrap().into())
-            .collect();
+        let path = self.get_lineage_path(&dataset.name, &pipeline_hash);
+        let legacy_files = read_legacy_lineage_files(&path)?;
 
+        let mut seen = HashSet::new();
+        let mut fragments = Vec::new();
+        let (entries, _) = read_lineage_index(&path)?;
+        for fragment_id in entries.into_iter().map(|entry| entry.fragment_id).chain(legacy_files) {
+            if seen.insert(fragment_id.to_string()) {
+                fragments.push(fragment_id);
+            }
+        }
         Ok(fragments)
     }
 
@@ -156,11 +347,286 @@ impl Local {
         pipeline_hash: &str,
         fragment_id: &str,
     ) -> Result<FragmentLineage, DaemonError> {
-        let path = self.get_pipeline_path(&dataset.name, &pipeline_hash).join("lineage");
-        let file_path = path.join(fragment_id);
+        let path = self.get_lineage_path(&dataset.name, &pipeline_hash);
+
+        let location = {
+            let segment_index = segment_index(&path);
+            let mut segment_index = segment_index.lock().unwrap();
+            segment_index.load(&path)?;
+            segment_index.locations.get(fragment_id).copied()
+        };
+        if let Some((offset, length)) = location {
+            let mut segment = File::open(path.join(LINEAGE_SEGMENT))?;
+            segment.seek(SeekFrom::Start(offset))?;
+            let mut record = vec![0; length as usize];
+            segment.read_exact(&mut record)?;
+            return Ok(serde_json::from_slice(&record)?);
+        }
 
-        let string = fs::read_to_string(file_path)?;
+        // Legacy lineage files are named after the fragment id, so ids which are not a single file name cannot refer to one.
+        if is_reserved_lineage_file(fragment_id) || !utils::is_contained_path(fragment_id) || fragment_id.contains('/')
+        {
+            return Err(DaemonError::NotFound);
+        }
+        let string = fs::read_to_string(path.join(fragment_id))?;
         let fragment_lineage: FragmentLineage = serde_json::from_str(&string)?;
         Ok(fragment_lineage)
     }
+
+    pub fn get_all_pipeline_fragment_lineages(
+        &self,
+        dataset: &DatasetConfig,
+        pipeline_hash: &str,
+    ) -> Result<Vec<FragmentLineage>, DaemonError> {
+        let path = self.get_lineage_path(&dataset.name, &pipeline_hash);
+
+        let mut lineages: Vec<FragmentLineage> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let mut add = |lineage: FragmentLineage| {
+            let fragment_id = lineage.description.metadata.fragment_id.to_string();
+            match positions.get(&fragment_id) {
+                Some(&position) => lineages[position] = lineage,
+                None => {
+                    positions.insert(fragment_id, lineages.len());
+                    lineages.push(lineage);
+                }
+            }
+        };
+
+        // Legacy files predate the segment, so records in the segment take precedence.
+        for file_name in read_legacy_lineage_files(&path)? {
+            let string = fs::read_to_string(path.join(file_name))?;
+            add(serde_json::from_str(&string)?);
+        }
+
+        // Only records referenced by the index are read, which excludes any left behind by an interrupted append.
+        let mut locations: Vec<(u64, u64)> = {
+            let segment_index = segment_index(&path);
+            let mut segment_index = segment_index.lock().unwrap();
+            segment_index.load(&path)?;
+            segment_index.locations.values().copied().collect()
+        };
+        if !locations.is_empty() {
+            locations.sort();
+            let mut segment = BufReader::new(File::open(path.join(LINEAGE_SEGMENT))?);
+            for (offset, length) in locations {
+                segment.seek(SeekFrom::Start(offset))?;
+                let mut record = vec![0; length as usize];
+                segment.read_exact(&mut record)?;
+                add(serde_json::from_slice(&record)?);
+            }
+        }
+        Ok(lineages)
+    }
+}
+
+/// Segment to which the lineage records of a run are appended, one JSON document per line.
+const LINEAGE_SEGMENT: &str = "segment.ndjson";
+/// Index of the lineage segment, mapping fragment ids to the location of their latest record.
+const LINEAGE_INDEX: &str = "index.ndjson";
+
+/// Locations of the latest record of each fragment in the lineage segment of a run, as read from its index.
+#[derive(Default)]
+struct SegmentIndex {
+    /// Length of the complete entries in the index file when it was read, used to detect changes made outside of this
+    /// process.
+    index_length: u64,
+    /// End of the last indexed record in the segment.
+    segment_length: u64,
+    locations: HashMap<String, (u64, u64)>,
+}
+
+impl SegmentIndex {
+    /// Reads the index file, unless it has not changed since it was last read.
+    fn load(&mut self, path: &Path) -> Result<(), DaemonError> {
+        let index_length = match fs::metadata(path.join(LINEAGE_INDEX)) {
+            Ok(metadata) => metadata.len(),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(error.into()),
+        };
+        if index_length == self.index_length {
+            return Ok(());
+        }
+        let (entries, complete_length) = read_lineage_index(path)?;
+        self.segment_length = entries
+            .iter()
+            .map(|entry| entry.offset + entry.length)
+            .max()
+            .unwrap_or(0);
+        self.locations = entries
+            .into_iter()
+            .map(|entry| (entry.fragment_id, (entry.offset, entry.length)))
+            .collect();
+        self.index_length = complete_length;
+        Ok(())
+    }
+}
+
+/// Segment indexes of the runs which were accessed, by the path of their lineage folder. Each run has its own lock,
+/// which serializes appends to its segment such that the offsets in its index always match the segment.
+static SEGMENT_INDEXES: Mutex<BTreeMap<PathBuf, Arc<Mutex<SegmentIndex>>>> = Mutex::new(BTreeMap::new());
+
+fn segment_index(path: &Path) -> Arc<Mutex<SegmentIndex>> {
+    SEGMENT_INDEXES
+        .lock()
+        .unwrap()
+        .entry(path.to_path_buf())
+        .or_default()
+        .clone()
+}
+
+/// Drops the segment indexes of runs within a removed folder.
+pub fn forget_segment_indexes(removed_path: &Path) {
+    SEGMENT_INDEXES
+        .lock()
+        .unwrap()
+        .retain(|path, _| !path.starts_with(removed_path));
+}
+
+#[derive(Serialize, Deserialize)]
+struct LineageIndexEntry {
+    fragment_id: String,
+    offset: u64,
+    length: u64,
+}
+
+fn is_reserved_lineage_file(file_name: &str) -> bool {
+    file_name == LINEAGE_SEGMENT || file_name == LINEAGE_INDEX
+}
+
+/// Reads the index of a lineage segment, along with the length of its complete entries. Returns an empty list when
+/// nothing has been appended yet. A trailing line without a newline is the remainder of an interrupted append, and is
+/// ignored.
+fn read_lineage_index(path: &Path) -> Result<(Vec<LineageIndexEntry>, u64), DaemonError> {
+    let bytes = match fs::read(path.join(LINEAGE_INDEX)) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+        Err(error) => return Err(error.into()),
+    };
+    let complete_length = bytes
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |position| position + 1);
+    let entries = bytes[..complete_length]
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| -> Result<LineageIndexEntry, DaemonError> { Ok(serde_json::from_slice(line)?) })
+        .collect::<Result<_, _>>()?;
+    Ok((entries, complete_length as u64))
+}
+
+/// Lists lineage stored in the legacy format of one file per fragment, named after the fragment id.
+fn read_legacy_lineage_files(path: &Path) -> Result<Vec<String>, DaemonError> {
+    let mut file_names = Vec::new();
+    for direntry in fs::read_dir(path)? {
+        let file_name = direntry?.file_name().to_string_lossy().to_string();
+        if !is_reserved_lineage_file(&file_name) {
+            file_names.push(file_name);
+        }
+    }
+    file_names.sort();
+    Ok(file_names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Backend;
+    use crate::pipeline::lineage::tests::fragment_lineage;
+
+    fn local_dataset(name: &str) -> (Local, DatasetConfig) {
+        let local = Local {
+            path: format!(
+                "{}/",
+                std::env::temp_dir()
+                    .join(format!("daemon-lineage-test-{}", std::process::id()))
+                    .display()
+            ),
+        };
+        let dataset = DatasetConfig {
+            name: name.to_owned(),
+            backend: Backend::Local(local.clone()),
+            description: String::new(),
+            retention: None,
+        };
+        (local, dataset)
+    }
+
+    #[test]
+    fn latest_record_of_a_fragment_is_returned() {
+        let (local, dataset) = local_dataset("latest");
+        local
+            .store_pipeline_fragment_lineage(&dataset, "run", &fragment_lineage("f1", "fragmenter", &[], &["a.csv"]))
+            .unwrap();
+        local
+            .store_pipeline_fragment_lineage(&dataset, "run", &fragment_lineage("f2", "clean", &["f1"], &["b.csv"]))
+            .unwrap();
+        local
+            .store_pipeline_fragment_lineage(&dataset, "run", &fragment_lineage("f1", "fragmenter", &[], &["c.csv"]))
+            .unwrap();
+
+        let f1 = local.get_pipeline_fragment_lineage(&dataset, "run", "f1").unwrap();
+        let f2 = local.get_pipeline_fragment_lineage(&dataset, "run", "f2").unwrap();
+        let fragments = local.get_pipeline_fragment_lineages(&dataset, "run").unwrap();
+        let missing = local.get_pipeline_fragment_lineage(&dataset, "run", "f3");
+        local.remove_pipeline_execution(&dataset, "run").unwrap();
+
+        assert_eq!(f1.description.files[0].name, "c.csv");
+        assert_eq!(f2.description.files[0].name, "b.csv");
+        assert_eq!(fragments, vec!["f1", "f2"]);
+        assert!(matches!(missing, Err(DaemonError::NotFound)));
+    }
+
+    #[test]
+    fn removed_runs_do_not_keep_their_segment_index() {
+        let (local, dataset) = local_dataset("removed");
+        local
+            .store_pipeline_fragment_lineage(&dataset, "run", &fragment_lineage("f1", "fragmenter", &[], &["a.csv"]))
+            .unwrap();
+        local.remove_pipeline_execution(&dataset, "run").unwrap();
+        local
+            .store_pipeline_fragment_lineage(&dataset, "run", &fragment_lineage("f2", "fragmenter", &[], &["b.csv"]))
+            .unwrap();
+
+        let f1 = local.get_pipeline_fragment_lineage(&dataset, "run", "f1");
+        let f2 = local.get_pipeline_fragment_lineage(&dataset, "run", "f2").unwrap();
+        local.remove_pipeline_execution(&dataset, "run").unwrap();
+
+        assert!(matches!(f1, Err(DaemonError::NotFound)));
+        assert_eq!(f2.description.files[0].name, "b.csv");
+    }
+
+    #[test]
+    fn interrupted_appends_are_ignored_and_overwritten() {
+        let (local, dataset) = local_dataset("interrupted");
+        local
+            .store_pipeline_fragment_lineage(&dataset, "run", &fragment_lineage("f1", "fragmenter", &[], &["a.csv"]))
+            .unwrap();
+        let path = local.get_lineage_path(&dataset.name, "run");
+        let append = |file: &str, bytes: &[u8]| {
+            let mut file = OpenOptions::new().append(true).open(path.join(file)).unwrap();
+            file.write_all(bytes).unwrap();
+        };
+        // A record whose index entry was never written, followed by a partial record and a partial index entry.
+        append(
+            LINEAGE_SEGMENT,
+            &serde_json::to_vec(&fragment_lineage("f2", "clean", &["f1"], &["b.csv"])).unwrap(),
+        );
+        append(LINEAGE_SEGMENT, b"\n{\"desc");
+        append(LINEAGE_INDEX, b"{\"fragment_id\":\"f2\"");
+
+        let interrupted = local.get_all_pipeline_fragment_lineages(&dataset, "run").unwrap();
+        local
+            .store_pipeline_fragment_lineage(&dataset, "run", &fragment_lineage("f3", "clean", &["f1"], &["c.csv"]))
+            .unwrap();
+        forget_segment_indexes(&path);
+        let fragments = local.get_pipeline_fragment_lineages(&dataset, "run").unwrap();
+        let all = local.get_all_pipeline_fragment_lineages(&dataset, "run").unwrap();
+        local.remove_pipeline_execution(&dataset, "run").unwrap();
+
+        assert_eq!(interrupted.len(), 1);
+        assert_eq!(fragments, vec!["f1", "f3"]);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].description.files[0].name, "c.csv");
+    }
 }
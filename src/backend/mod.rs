@@ -2,11 +2,16 @@
 //! Different storage backends can be implemented by implementing the functions for the other Enum variants.
 use crate::dataset::DatasetConfig;
 use crate::error::DaemonError;
+use crate::pipeline::index::PipelineIndex;
+use crate::pipeline::models::{PipelineStorageUsage, RemovalReport, ResultMetadata};
 use iterum_rust::pipeline::PipelineExecution;
 use iterum_rust::provenance::FragmentLineage;
 use iterum_rust::vc::{Commit, Dataset};
 use local::Local;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
 
 pub mod local;
 
@@ -58,6 +63,73 @@ impl Backend {
         }
     }
 
+    /// Describes how stored files of one dataset are shared with another dataset, for example when forking a dataset.
+    pub fn fork_files(&self, source_path: &str, target_path: &str, files: &[String]) -> Result<(), DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.fork_files(source_path, target_path, files),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to list the files which are stored for a dataset, together with their size in bytes.
+    pub fn get_stored_files(&self, dataset_path: &str) -> Result<Vec<(String, u64)>, DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.get_stored_files(dataset_path),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to remove stored files from a dataset.
+    pub fn remove_stored_files(&self, dataset_path: &str, files: &[String]) -> Result<(), DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.remove_stored_files(dataset_path, files),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to list the stored files of a dataset which are exported in a bundle, optionally including its pipeline runs.
+    pub fn bundle_files(&self, dataset: &DatasetConfig, include_runs: bool) -> Result<Vec<String>, DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.bundle_files(dataset, include_runs),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to export a dataset, with the given version history and stored files, as a bundle which is written to `writer`.
+    pub fn export_bundle(
+        &self,
+        dataset: &DatasetConfig,
+        vc_dataset: &Dataset,
+        files: &[String],
+        writer: &mut dyn Write,
+    ) -> Result<(), DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.export_bundle(dataset, vc_dataset, files, writer),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how the files of an unpacked bundle are stored in the backend.
+    pub fn import_bundle_files(
+        &self,
+        dataset_path: &str,
+        bundle_dir: &Path,
+        files: &[String],
+    ) -> Result<(), DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.import_bundle_files(dataset_path, bundle_dir, files),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to move a dataset as a whole to another name on the storage backend.
+    pub fn rename_dataset(&self, dataset_path: &str, new_dataset_path: &str) -> Result<(), DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.rename_dataset(dataset_path, new_dataset_path),
+            _ => unimplemented!(),
+        }
+    }
+
     /// Describes how to remove a dataset as a whole from the storage backend.
     pub fn remove_dataset(&self, dataset_path: &str) -> Result<(), DaemonError> {
         match self {
@@ -89,40 +161,91 @@ impl Backend {
         }
     }
 
-    /// Describes how to store a pipeline execution in the storage backend.
+    /// Describes how to store a pipeline execution in the storage backend, and registers it in the pipeline index.
     pub fn store_pipeline_execution(
         &self,
         dataset: &DatasetConfig,
         pipeline_execution: &PipelineExecution,
+        pipeline_index: &PipelineIndex,
     ) -> Result<(), DaemonError> {
         match self {
-            Backend::Local(backend) => backend.store_pipeline_execution(dataset, pipeline_execution),
+            Backend::Local(backend) => backend.store_pipeline_execution(dataset, pipeline_execution)?,
             _ => unimplemented!(),
         }
+        pipeline_index.insert(&pipeline_execution.pipeline_run.pipeline_run_hash, &dataset.name)
     }
 
-    /// Describes how to remove a specific pipeline execution from the storage backend.
-    pub fn remove_pipeline_execution(&self, dataset: &DatasetConfig, pipeline_hash: &str) -> Result<(), DaemonError> {
-        match self {
+    /// Describes how to remove a specific pipeline execution from the storage backend. Returns what was removed.
+    /// The pipeline execution is removed from the pipeline index as well, also when it was already gone from the storage backend.
+    pub fn remove_pipeline_execution(
+        &self,
+        dataset: &DatasetConfig,
+        pipeline_hash: &str,
+        pipeline_index: &PipelineIndex,
+    ) -> Result<RemovalReport, DaemonError> {
+        let report = match self {
             Backend::Local(backend) => backend.remove_pipeline_execution(dataset, pipeline_hash),
             _ => unimplemented!(),
+        };
+        pipeline_index.remove(pipeline_hash)?;
+        report
+    }
+
+    /// Describes how to store a JSON document next to a pipeline execution, such as its status. Documents are identified by their name.
+    pub fn store_pipeline_document<T: Serialize>(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+        name: &str,
+        document: &T,
+    ) -> Result<(), DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.store_pipeline_document(dataset_path, pipeline_hash, name, document),
+            _ => unimplemented!(),
         }
     }
 
-    /// Describes how to store results of a pipeline in the storage backend.
+    /// Describes how to retrieve a JSON document stored next to a pipeline execution.
+    pub fn get_pipeline_document<T: DeserializeOwned>(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+        name: &str,
+    ) -> Result<T, DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.get_pipeline_document(dataset_path, pipeline_hash, name),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to determine the storage used by the results and lineage of a pipeline execution.
+    pub fn get_pipeline_storage_usage(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+    ) -> Result<PipelineStorageUsage, DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.get_pipeline_storage_usage(dataset_path, pipeline_hash),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to store results of a pipeline in the storage backend, and registers the pipeline execution in the pipeline index.
     pub fn store_pipeline_result_files(
         &self,
         dataset: &DatasetConfig,
         pipeline_result_paths: &[(String, String)],
         pipeline_hash: &str,
         tmp_files_path: &str,
-    ) -> Result<(), std::io::Error> {
+        pipeline_index: &PipelineIndex,
+    ) -> Result<(), DaemonError> {
         match self {
             Backend::Local(backend) => {
-                backend.store_pipeline_result_files(dataset, pipeline_result_paths, pipeline_hash, tmp_files_path)
+                backend.store_pipeline_result_files(dataset, pipeline_result_paths, pipeline_hash, tmp_files_path)?
             }
             _ => unimplemented!(),
         }
+        pipeline_index.insert(pipeline_hash, &dataset.name)
     }
 
     /// Describes how to retrieve a list of results of a pipeline in the storage backend. Returns a list of filenames, not the data itself
@@ -146,17 +269,62 @@ impl Backend {
         }
     }
 
-    /// Describes how to store a FragmentLineage from a pipeline in the storage backend.
+    /// Describes how to copy a specific pipeline result from the storage backend to a local file, without reading it into memory.
+    pub fn copy_pipeline_result(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+        file_name: &str,
+        target: &Path,
+    ) -> Result<(), DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.copy_pipeline_result(dataset_path, pipeline_hash, file_name, target),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to determine the metadata, such as the content hash, of a pipeline result in the storage backend.
+    pub fn get_pipeline_result_metadata(
+        &self,
+        dataset_path: &str,
+        pipeline_hash: &str,
+        file_name: &str,
+    ) -> Result<ResultMetadata, DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.get_pipeline_result_metadata(dataset_path, pipeline_hash, file_name),
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to write all results of a pipeline as a gzipped tarball to `writer`, optionally together with the pipeline execution and its lineage.
+    pub fn export_pipeline_results(
+        &self,
+        dataset: &DatasetConfig,
+        pipeline_hash: &str,
+        include_provenance: bool,
+        writer: &mut dyn Write,
+    ) -> Result<(), DaemonError> {
+        match self {
+            Backend::Local(backend) => {
+                backend.export_pipeline_results(dataset, pipeline_hash, include_provenance, writer)
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Describes how to store a FragmentLineage from a pipeline in the storage backend, and registers the pipeline execution in the pipeline index.
     pub fn store_pipeline_fragment_lineage(
         &self,
         dataset: &DatasetConfig,
         pipeline_hash: &str,
         fragment: &FragmentLineage,
+        pipeline_index: &PipelineIndex,
     ) -> Result<(), DaemonError> {
         match self {
-            Backend::Local(backend) => backend.store_pipeline_fragment_lineage(dataset, pipeline_hash, fragment),
+            Backend::Local(backend) => backend.store_pipeline_fragment_lineage(dataset, pipeline_hash, fragment)?,
             _ => unimplemented!(),
         }
+        pipeline_index.insert(pipeline_hash, &dataset.name)
     }
 
     /// Describes how to retrieve all lineage information from the storage backend. Returns a list of fragment hashes.
@@ -183,4 +351,16 @@ impl Backend {
             _ => unimplemented!(),
         }
     }
+
+    /// Describes how to retrieve the latest FragmentLineage of every fragment of a pipeline in a single pass.
+    pub fn get_all_pipeline_fragment_lineages(
+        &self,
+        dataset: &DatasetConfig,
+        pipeline_hash: &str,
+    ) -> Result<Vec<FragmentLineage>, DaemonError> {
+        match self {
+            Backend::Local(backend) => backend.get_all_pipeline_fragment_lineages(dataset, pipeline_hash),
+            _ => unimplemented!(),
+        }
+    }
 }
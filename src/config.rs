@@ -1,5 +1,8 @@
 //! Module which contains storage which is shared by the different endpoint handlers. It has a reference to the `local_config` which is a key-value store used to store DatasetConfigs, but also
 //! a HashMap where the available data sets are stored in memory, for quicker access.
+use crate::openlineage::OpenLineageEmitter;
+use crate::pipeline::index::{LineageIndex, PipelineIndex, PromotionIndex};
+use crate::pipeline::retention::RetentionLog;
 use iterum_rust::vc::Dataset;
 use sled::Db;
 use std::collections::HashMap;
@@ -11,4 +14,14 @@ pub struct Config {
     pub local_config: Db,
     /// HashMap which stores the metadata of datasets in memory, instead of constantly having to retrieve data from a storage backend.
     pub datasets: RwLock<HashMap<String, Dataset>>,
+    /// Index which maps pipeline hashes onto the dataset in which they are stored.
+    pub pipeline_index: PipelineIndex,
+    /// Index which maps files of datasets onto the fragments of pipeline executions which consumed them.
+    pub lineage_index: LineageIndex,
+    /// Index which maps commits of datasets onto the pipeline executions whose results were promoted into them.
+    pub promotion_index: PromotionIndex,
+    /// Emitter of OpenLineage events for pipeline executions, if configured.
+    pub openlineage: Option<OpenLineageEmitter>,
+    /// Log of the pipeline executions which were deleted by retention policies.
+    pub retention_log: RetentionLog,
 }
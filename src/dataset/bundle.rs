@@ -0,0 +1,104 @@
+//! Contains the manifest of dataset bundles. A bundle is a gzipped tarball which contains everything needed to move a dataset to another daemon:
+//! the `dataset.json`, all stored file versions in `data/`, and optionally the pipeline runs and their lineage in `runs/`.
+//! The manifest stores a digest of each of these files, such that the integrity of a bundle can be checked when it is imported.
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Name of the manifest file in the root of a bundle.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Version of the bundle format, which is increased whenever the layout of bundles changes.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// The manifest of a bundle. Contains the DatasetConfig of the exported dataset, and the SHA-256 digest of every file in the bundle.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BundleManifest {
+    pub version: u32,
+    pub dataset: DatasetConfig,
+    pub files: BTreeMap<String, String>,
+}
+
+impl BundleManifest {
+    /// Creates a manifest for files with the given digests.
+    pub fn new(dataset: &DatasetConfig, files: BTreeMap<String, String>) -> BundleManifest {
+        BundleManifest {
+            version: BUNDLE_VERSION,
+            dataset: dataset.clone(),
+            files,
+        }
+    }
+
+    /// Checks whether the unpacked bundle in `root` matches the manifest. Each file in the manifest should be a relative path within the bundle, present with the correct digest,
+    /// and no other files should be present.
+    pub fn verify(&self, root: &Path) -> Result<(), DaemonError> {
+        if self.version != BUNDLE_VERSION {
+            return Err(DaemonError::BadRequest(format!(
+                "Unsupported bundle version {}.",
+                self.version
+            )));
+        }
+        if !self.files.contains_key("dataset.json") {
+            return Err(DaemonError::BadRequest(
+                "Bundle does not contain a dataset.json.".to_owned(),
+            ));
+        }
+        if let Some(file) = self.files.keys().find(|file| !utils::is_contained_path(file)) {
+            return Err(DaemonError::BadRequest(format!(
+                "File {} in the manifest is not contained in the bundle.",
+                file
+            )));
+        }
+        for file in utils::list_files_recursively(root)? {
+            if file != MANIFEST_FILE && !self.files.contains_key(&file) {
+                return Err(DaemonError::BadRequest(format!(
+                    "File {} is not listed in the manifest.",
+                    file
+                )));
+            }
+        }
+        for (file, digest) in &self.files {
+            let actual_digest = utils::file_digest(&root.join(file))
+                .map_err(|_| DaemonError::BadRequest(format!("File {} is missing from the bundle.", file)))?;
+            if actual_digest != *digest {
+                return Err(DaemonError::BadRequest(format!(
+                    "Digest of file {} does not match.",
+                    file
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::local::Local;
+    use crate::backend::Backend;
+
+    fn manifest(files: &[&str]) -> BundleManifest {
+        BundleManifest {
+            version: BUNDLE_VERSION,
+            dataset: DatasetConfig {
+                name: "bundle".to_owned(),
+                backend: Backend::Local(Local { path: "./".to_owned() }),
+                description: String::new(),
+                retention: None,
+            },
+            files: files.iter().map(|file| (file.to_string(), String::new())).collect(),
+        }
+    }
+
+    #[test]
+    fn manifests_with_files_outside_the_bundle_are_refused() {
+        let root = std::env::temp_dir().join(format!("daemon-bundle-test-{}", std::process::id()));
+        for file in &["../dataset.json", "/etc/passwd", "data/../../secret"] {
+            let result = manifest(&["dataset.json", file]).verify(&root);
+            assert!(matches!(result, Err(DaemonError::BadRequest(message)) if message.contains(file)));
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Contains the garbage collection of stored file versions. File versions which cannot be reached from any branch head are unreachable, and can be removed from the storage backend.
+//! Commits which are used as input of a pipeline execution are also treated as roots, such that the files referenced by pipeline results and lineage are never removed.
+use crate::dataset::history;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use iterum_rust::vc::Dataset;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Report of a garbage collection run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub reachable_files: usize,
+    pub unreachable_files: Vec<String>,
+    pub reclaimable_bytes: u64,
+}
+
+/// Retrieves the commits from which reachability is computed: the heads of all branches, and the input commits of all pipeline executions on the dataset.
+pub fn root_commits(dataset_config: &DatasetConfig, vc_dataset: &Dataset) -> Result<Vec<String>, DaemonError> {
+    let mut roots: Vec<String> = vc_dataset
+        .branches
+        .values()
+        .map(|branch| branch.head.to_string())
+        .filter(|head| vc_dataset.commits.contains_key(head))
+        .collect();
+
+    let pipeline_hashes = dataset_config.backend.get_pipeline_executions(&dataset_config.name)?;
+    for pipeline_hash in pipeline_hashes {
+        // A run without an execution.json has no known input commit, so it does not keep any commit reachable.
+        let pipeline_execution = match dataset_config
+            .backend
+            .get_pipeline_execution(&dataset_config.name, &pipeline_hash)
+        {
+            Ok(pipeline_execution) => pipeline_execution,
+            Err(DaemonError::NotFound) => {
+                warn!(
+                    "Pipeline execution {} of dataset {} has no execution.json, skipping it for garbage collection.",
+                    pipeline_hash, dataset_config.name
+                );
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        let commit_hash = pipeline_execution.pipeline_run.input_dataset_commit_hash;
+        if vc_dataset.commits.contains_key(&commit_hash) {
+            roots.push(commit_hash);
+        }
+    }
+    Ok(roots)
+}
+
+/// Computes which stored files are unreachable, and removes them from the storage backend unless `dry_run` is set.
+pub fn collect_garbage(
+    dataset_config: &DatasetConfig,
+    vc_dataset: &Dataset,
+    dry_run: bool,
+) -> Result<GcReport, DaemonError> {
+    let roots = root_commits(dataset_config, vc_dataset)?;
+    let reachable: HashSet<String> = history::stored_files(&history::reachable_commits(vc_dataset, &roots)?)
+        .into_iter()
+        .collect();
+
+    let (unreachable_files, sizes): (Vec<String>, Vec<u64>) = dataset_config
+        .backend
+        .get_stored_files(&dataset_config.name)?
+        .into_iter()
+        .filter(|(file, _)| !reachable.contains(file))
+        .unzip();
+
+    if !dry_run {
+        info!(
+            "Removing {} unreachable files from dataset {}",
+            unreachable_files.len(),
+            dataset_config.name
+        );
+        dataset_config
+            .backend
+            .remove_stored_files(&dataset_config.name, &unreachable_files)?;
+    }
+
+    Ok(GcReport {
+        dry_run,
+        reachable_files: reachable.len(),
+        unreachable_files,
+        reclaimable_bytes: sizes.iter().sum(),
+    })
+}
@@ -0,0 +1,228 @@
+//! Helper functions to walk through the version history of a dataset.
+use iterum_rust::utils;
+use iterum_rust::vc::{error::VersionControlError, Branch, Commit, Dataset, Deprecated, Diff};
+use std::collections::HashSet;
+
+/// Finds a branch of a dataset, either by its hash or by its name.
+pub fn find_branch<'a>(dataset: &'a Dataset, branch: &str) -> Result<&'a Branch, VersionControlError> {
+    dataset
+        .branches
+        .get(branch)
+        .or_else(|| dataset.branches.values().find(|candidate| candidate.name == branch))
+        .ok_or(VersionControlError::BranchNotFound)
+}
+
+/// Retrieves the commits leading up to (and including) the given commit, ordered from the root commit onwards.
+pub fn commit_chain<'a>(dataset: &'a Dataset, commit_hash: &str) -> Result<Vec<&'a Commit>, VersionControlError> {
+    let mut chain: Vec<&Commit> = Vec::new();
+    let mut next = Some(commit_hash.to_owned());
+    while let Some(hash) = next {
+        let commit = dataset.commits.get(&hash).ok_or(VersionControlError::CommitNotFound)?;
+        next = commit.parent.clone();
+        chain.push(commit);
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+/// Retrieves all commits which are reachable from the given commits, by following the parents of each commit.
+pub fn reachable_commits<'a>(dataset: &'a Dataset, roots: &[String]) -> Result<Vec<&'a Commit>, VersionControlError> {
+    let mut visited: HashSet<&str> = HashSet::new();
+    let mut reachable: Vec<&Commit> = Vec::new();
+    for root in roots {
+        for commit in commit_chain(dataset, root)? {
+            if visited.insert(&commit.hash) {
+                reachable.push(commit);
+            }
+        }
+    }
+    Ok(reachable)
+}
+
+/// Lists the files which are stored in the backend for a set of commits, which are the files added or updated by those commits.
+pub fn stored_files(commits: &[&Commit]) -> Vec<String> {
+    commits
+        .iter()
+        .flat_map(|commit| commit.diff.added.iter().chain(commit.diff.updated.iter()))
+        .cloned()
+        .collect()
+}
+
+/// Creates a new dataset whose history consists of the given commit and its ancestors in `dataset`.
+/// The commits are replayed in order, so the version tree of the new dataset is built up the same way as when the CLI pushes commits.
+pub fn replay_history(dataset: &Dataset, commit_hash: &str) -> Result<Dataset, VersionControlError> {
+    let mut replayed = Dataset::new();
+    for commit in commit_chain(dataset, commit_hash)? {
+        if !replayed.branches.contains_key(&commit.branch) {
+            // Branches start at the commit they were branched off from.
+            let mut branch = dataset
+                .branches
+                .get(&commit.branch)
+                .ok_or(VersionControlError::BranchNotFound)?
+                .clone();
+            branch.head = commit.parent.clone().unwrap_or_default();
+            replayed = replayed.add_branch(&branch)?;
+        }
+        replayed = replayed.add_commit(commit)?;
+    }
+    Ok(replayed)
+}
+
+/// Finds the commit which stored the version of `file` that is present at the given commit, which is the last commit up to it that added or updated the file.
+/// Returns `None` when the file is not present at the commit.
+pub fn file_version<'a>(
+    dataset: &'a Dataset,
+    commit_hash: &str,
+    file: &str,
+) -> Result<Option<&'a str>, VersionControlError> {
+    let chain = commit_chain(dataset, commit_hash)?;
+    match chain.last() {
+        Some(commit) if commit.files.iter().any(|present| present == file) => {}
+        _ => return Ok(None),
+    }
+    Ok(chain
+        .iter()
+        .rev()
+        .find(|commit| {
+            let stored_name = stored_file_name(file, &commit.hash);
+            commit.diff.added.contains(&stored_name) || commit.diff.updated.contains(&stored_name)
+        })
+        .map(|commit| commit.hash.as_str()))
+}
+
+/// Name under which a version of a file is stored in the backend. Each version of a file is stored in a folder named after the file, in a file named after the commit that added it.
+pub fn stored_file_name(file: &str, commit_hash: &str) -> String {
+    format!("{}/{}", file, commit_hash)
+}
+
+/// Creates a new commit on top of the head of a branch, containing the given files. Files already present in the parent commit are marked as updated.
+/// When the dataset does not have any branches yet, the branch is created as well.
+pub fn commit_files(
+    mut dataset: Dataset,
+    branch_name: &str,
+    name: &str,
+    desc: &str,
+    files: &[String],
+) -> Result<(Dataset, Commit), VersionControlError> {
+    if dataset.branches.is_empty() {
+        let branch = Branch {
+            hash: utils::create_random_hash(),
+            name: branch_name.to_owned(),
+            head: String::new(),
+        };
+        dataset = dataset.add_branch(&branch)?;
+    }
+    let branch = find_branch(&dataset, branch_name)?.clone();
+    let parent = dataset.commits.get(&branch.head);
+
+    let hash = utils::create_random_hash();
+    let mut commit_files: Vec<String> = parent.map(|commit| commit.files.clone()).unwrap_or_default();
+    let mut diff = Diff {
+        added: Vec::new(),
+        updated: Vec::new(),
+        removed: Vec::new(),
+    };
+    for file in files {
+        if commit_files.contains(file) {
+            diff.updated.push(stored_file_name(file, &hash));
+        } else {
+            diff.added.push(stored_file_name(file, &hash));
+            commit_files.push(file.to_string());
+        }
+    }
+
+    let commit = Commit {
+        hash,
+        parent: parent.map(|commit| commit.hash.to_string()),
+        branch: branch.hash,
+        name: Some(name.to_owned()),
+        desc: desc.to_owned(),
+        files: commit_files,
+        diff,
+        deprecated: Deprecated {
+            value: false,
+            reason: String::new(),
+        },
+    };
+    let dataset = dataset.add_commit(&commit)?;
+    Ok((dataset, commit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a dataset with a single branch of three commits, each adding one file. Returns the dataset and the commit hashes in order.
+    fn linear_dataset() -> (Dataset, Vec<String>) {
+        let mut dataset = Dataset::new();
+        let mut hashes = Vec::new();
+        for file in &["a.txt", "b.txt", "c.txt"] {
+            let (next, commit) = commit_files(dataset, "master", file, "", &[file.to_string()]).unwrap();
+            dataset = next;
+            hashes.push(commit.hash);
+        }
+        (dataset, hashes)
+    }
+
+    #[test]
+    fn commit_chain_is_ordered_from_the_root() {
+        let (dataset, hashes) = linear_dataset();
+        let chain: Vec<String> = commit_chain(&dataset, &hashes[2])
+            .unwrap()
+            .into_iter()
+            .map(|commit| commit.hash.to_string())
+            .collect();
+        assert_eq!(chain, hashes);
+    }
+
+    #[test]
+    fn commit_chain_of_an_unknown_commit_fails() {
+        let (dataset, _) = linear_dataset();
+        assert!(commit_chain(&dataset, "unknown").is_err());
+    }
+
+    #[test]
+    fn replay_history_ends_at_the_commit() {
+        let (dataset, hashes) = linear_dataset();
+        let replayed = replay_history(&dataset, &hashes[1]).unwrap();
+        assert!(replayed.commits.contains_key(&hashes[0]));
+        assert!(replayed.commits.contains_key(&hashes[1]));
+        assert!(!replayed.commits.contains_key(&hashes[2]));
+        assert_eq!(replayed.commits[&hashes[1]].files, vec!["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn file_version_is_the_last_commit_changing_the_file() {
+        let (dataset, hashes) = linear_dataset();
+        assert_eq!(
+            file_version(&dataset, &hashes[2], "a.txt").unwrap(),
+            Some(hashes[0].as_str())
+        );
+        assert_eq!(
+            file_version(&dataset, &hashes[2], "c.txt").unwrap(),
+            Some(hashes[2].as_str())
+        );
+        assert_eq!(file_version(&dataset, &hashes[1], "c.txt").unwrap(), None);
+
+        let (dataset, update) = commit_files(dataset, "master", "update", "", &["a.txt".to_owned()]).unwrap();
+        assert_eq!(
+            file_version(&dataset, &update.hash, "a.txt").unwrap(),
+            Some(update.hash.as_str())
+        );
+        assert_eq!(
+            file_version(&dataset, &hashes[2], "a.txt").unwrap(),
+            Some(hashes[0].as_str())
+        );
+    }
+
+    #[test]
+    fn stored_files_lists_every_stored_version() {
+        let (dataset, hashes) = linear_dataset();
+        let (dataset, update) = commit_files(dataset, "master", "update", "", &["a.txt".to_owned()]).unwrap();
+        let chain = commit_chain(&dataset, &update.hash).unwrap();
+        let files = stored_files(&chain);
+        assert!(files.contains(&stored_file_name("a.txt", &hashes[0])));
+        assert!(files.contains(&stored_file_name("a.txt", &update.hash)));
+        assert_eq!(files.len(), 4);
+    }
+}
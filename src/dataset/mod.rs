@@ -1,5 +1,9 @@
 //! Contains routes and models with regards to data versioning, and retrieving and storing files for a dataset.
+pub mod bundle;
+pub mod gc;
+pub mod history;
 pub mod models;
 pub mod routes;
+pub mod stats;
 pub use models::DatasetConfig;
 pub use routes::init_routes;
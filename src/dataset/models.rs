@@ -2,8 +2,10 @@
 
 use crate::backend::Backend;
 use crate::error::DaemonError;
+use crate::pipeline::index::PipelineIndex;
 use iterum_rust::vc::{Commit, Dataset};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 
 impl From<&DatasetConfig> for sled::IVec {
     fn from(dataset: &DatasetConfig) -> sled::IVec {
@@ -27,6 +29,47 @@ pub struct DatasetConfig {
     #[serde(flatten)]
     pub backend: Backend,
     pub description: String,
+    /// Policy which determines which pipeline executions of the dataset are deleted automatically. Without a policy, all pipeline executions are kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
+}
+
+/// Retention policy for the pipeline executions of a dataset. A pipeline execution is kept when it is one of the `keep_last` most recent ones,
+/// or when it is younger than `keep_days`. Unfinished, pinned and promoted pipeline executions are always kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    pub keep_last: Option<usize>,
+    pub keep_days: Option<i64>,
+}
+
+impl RetentionPolicy {
+    /// Whether the policy does not restrict anything, in which case all pipeline executions are kept.
+    pub fn keeps_everything(&self) -> bool {
+        self.keep_last.is_none() && self.keep_days.is_none()
+    }
+
+    /// Whether the policy keeps none of the pipeline executions which are not always kept, as each of its rules keeps nothing.
+    pub fn keeps_nothing(&self) -> bool {
+        !self.keeps_everything() && self.keep_last.unwrap_or(0) == 0 && self.keep_days.unwrap_or(0) <= 0
+    }
+}
+
+/// Request body used to fork a dataset. Either a commit or a branch should be given as the starting point of the fork.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ForkRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub commit: Option<String>,
+    pub branch: Option<String>,
+}
+
+/// Request body used to import a directory or tarball which is present on the server as a new dataset.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportRequest {
+    pub dataset: DatasetConfig,
+    pub source: String,
+    pub branch: Option<String>,
+    pub message: Option<String>,
 }
 
 impl DatasetConfig {
@@ -52,14 +95,24 @@ impl DatasetConfig {
         self.backend.remove_dataset(&self.name)
     }
 
+    pub fn fork_files(&self, source: &DatasetConfig, files: &[String]) -> Result<(), DaemonError> {
+        self.backend.fork_files(&source.name, &self.name, files)
+    }
+
     pub fn store_pipeline_result_files(
         &self,
         pipeline_result_paths: &[(String, String)],
         pipeline_hash: &str,
         tmp_files_path: &str,
-    ) -> Result<(), std::io::Error> {
-        self.backend
-            .store_pipeline_result_files(self, pipeline_result_paths, pipeline_hash, tmp_files_path)
+        pipeline_index: &PipelineIndex,
+    ) -> Result<(), DaemonError> {
+        self.backend.store_pipeline_result_files(
+            self,
+            pipeline_result_paths,
+            pipeline_hash,
+            tmp_files_path,
+            pipeline_index,
+        )
     }
 
     pub fn get_pipeline_results(&self, pipeline_hash: &str) -> Result<Vec<String>, DaemonError> {
@@ -69,4 +122,9 @@ impl DatasetConfig {
     pub fn get_pipeline_result(&self, pipeline_hash: &str, file_name: &str) -> Result<Vec<u8>, DaemonError> {
         self.backend.get_pipeline_result(&self.name, pipeline_hash, file_name)
     }
+
+    pub fn copy_pipeline_result(&self, pipeline_hash: &str, file_name: &str, target: &Path) -> Result<(), DaemonError> {
+        self.backend
+            .copy_pipeline_result(&self.name, pipeline_hash, file_name, target)
+    }
 }
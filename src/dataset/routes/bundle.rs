@@ -0,0 +1,280 @@
+//! Routes related to exporting and importing datasets as self-contained bundles, used to move datasets between daemons.
+
+use crate::backend::local::Local;
+use crate::backend::Backend;
+use crate::config;
+use crate::dataset::bundle::{BundleManifest, MANIFEST_FILE};
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::utils as daemon_utils;
+use actix_web::{get, post, web, HttpResponse};
+use async_std::prelude::*;
+use flate2::read::GzDecoder;
+use futures::StreamExt;
+use iterum_rust::utils;
+use iterum_rust::vc::Dataset;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Query parameters for exporting a bundle.
+#[derive(Deserialize, Debug)]
+pub struct ExportOptions {
+    #[serde(default)]
+    include_runs: bool,
+}
+
+/// Query parameters for importing a bundle.
+#[derive(Deserialize, Debug)]
+pub struct ImportOptions {
+    #[serde(default)]
+    force: bool,
+}
+
+/// Environment variable with the path of the local storage backend in which new datasets are stored when a bundle is imported.
+const BUNDLE_STORAGE_PATH_VARIABLE: &str = "BUNDLE_STORAGE_PATH";
+
+/// Export a dataset as a bundle. The bundle is a gzipped tarball containing the dataset.json, all stored files, and optionally the pipeline runs and their lineage.
+/// The bundle is streamed while it is being generated.
+#[get("/{dataset}/bundle")]
+async fn export_bundle(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    options: web::Query<ExportOptions>,
+) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    info!("Exporting dataset {} as bundle", dataset_path);
+
+    let dataset_config: DatasetConfig = config
+        .local_config
+        .get(&dataset_path)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    // Acquire read lock only while taking a snapshot of the dataset, such that a slow download does not block changes to datasets.
+    let (vc_dataset, files) = {
+        let datasets_ref = config.datasets.read().unwrap();
+        let vc_dataset = datasets_ref.get(&dataset_path).ok_or(DaemonError::NotFound)?.clone();
+        let files = dataset_config
+            .backend
+            .bundle_files(&dataset_config, options.include_runs)?;
+        (vc_dataset, files)
+    };
+    let bundle = daemon_utils::stream_writer(move |writer| {
+        dataset_config
+            .backend
+            .export_bundle(&dataset_config, &vc_dataset, &files, writer)
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/gzip")
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.tar.gz\"", dataset_path),
+        )
+        .streaming(bundle))
+}
+
+/// Determines the backend in which an imported bundle is stored. This is the backend of the dataset which is overwritten, or otherwise the local storage
+/// configured by `BUNDLE_STORAGE_PATH`. The backend stated in the manifest of the bundle is never used, as it describes the storage of another daemon.
+fn import_backend(existing_config: &Option<DatasetConfig>) -> Result<Backend, DaemonError> {
+    match existing_config {
+        Some(existing_config) => Ok(existing_config.backend.clone()),
+        None => {
+            let path = env::var(BUNDLE_STORAGE_PATH_VARIABLE).map_err(|_| {
+                DaemonError::BadRequest(format!(
+                    "Bundles can only overwrite existing datasets, as {} is not set.",
+                    BUNDLE_STORAGE_PATH_VARIABLE
+                ))
+            })?;
+            Ok(Backend::Local(Local {
+                path: storage_path(path),
+            }))
+        }
+    }
+}
+
+/// The local storage backend joins its path directly with dataset names, so the path has to end with a separator.
+fn storage_path(mut path: String) -> String {
+    if !path.ends_with('/') {
+        path.push('/');
+    }
+    path
+}
+
+/// Import a bundle as a dataset. The body of the request is the bundle itself. The integrity of the bundle is checked against its manifest before anything is stored.
+/// An existing dataset is only overwritten when `force` is set, in which case the backend of the existing dataset is used. Otherwise the dataset is stored
+/// in the local storage configured by `BUNDLE_STORAGE_PATH`.
+#[post("/{dataset}/bundle")]
+async fn import_bundle(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    options: web::Query<ImportOptions>,
+    payload: web::Payload,
+) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    info!("Importing bundle as dataset {}", dataset_path);
+
+    let existing_config: Option<DatasetConfig> = config.local_config.get(&dataset_path)?.map(|ivec| ivec.into());
+    if existing_config.is_some() && !options.force {
+        return Err(DaemonError::AlreadyExists);
+    }
+    let backend = import_backend(&existing_config)?;
+
+    // Store the bundle in a temporary folder and unpack it. The folder is removed regardless of the outcome.
+    let temp_path = format!("./.tmp/{}/", utils::create_random_hash());
+    fs::create_dir_all(&temp_path).expect("Could not create temporary file directory.");
+    let result = match receive_bundle(payload, Path::new(&temp_path)).await {
+        Ok(unpack_path) => store_bundle(&config, &dataset_path, existing_config, backend, &unpack_path),
+        Err(error) => Err(error),
+    };
+    if let Err(error) = fs::remove_dir_all(&temp_path) {
+        warn!("Could not remove temporary directory {}: {}", temp_path, error);
+    }
+    let dataset_config = result?;
+
+    Ok(HttpResponse::Ok().json(&dataset_config))
+}
+
+/// Receives a bundle into the temporary folder and unpacks it. Returns the folder with the unpacked bundle.
+async fn receive_bundle(mut payload: web::Payload, temp_path: &Path) -> Result<PathBuf, DaemonError> {
+    let bundle_path = temp_path.join("bundle.tar.gz");
+    let unpack_path = temp_path.join("bundle");
+
+    let mut f = async_std::fs::File::create(&bundle_path).await?;
+    while let Some(chunk) = payload.next().await {
+        let data = chunk?;
+        f.write_all(&data).await?;
+    }
+    f.flush().await?;
+
+    daemon_utils::unpack_without_links(GzDecoder::new(fs::File::open(&bundle_path)?), &unpack_path)?;
+    Ok(unpack_path)
+}
+
+/// Verifies an unpacked bundle against its manifest, and stores it as the dataset. The bundle is first stored under a temporary name, which only replaces
+/// the existing dataset once it is stored completely, so a failed import leaves the existing dataset intact.
+fn store_bundle(
+    config: &config::Config,
+    dataset_path: &str,
+    existing_config: Option<DatasetConfig>,
+    backend: Backend,
+    unpack_path: &Path,
+) -> Result<DatasetConfig, DaemonError> {
+    let manifest_string = fs::read_to_string(unpack_path.join(MANIFEST_FILE))
+        .map_err(|_| DaemonError::BadRequest("Bundle does not contain a manifest.".to_owned()))?;
+    let manifest: BundleManifest = serde_json::from_str(&manifest_string)?;
+    manifest.verify(unpack_path)?;
+
+    let vc_dataset: Dataset = serde_json::from_str(&fs::read_to_string(unpack_path.join("dataset.json"))?)?;
+    let dataset_config = DatasetConfig {
+        name: dataset_path.to_string(),
+        backend,
+        description: manifest.dataset.description.to_string(),
+        retention: match &existing_config {
+            Some(existing_config) => existing_config.retention.clone(),
+            None => manifest.dataset.retention.clone(),
+        },
+    };
+    let files: Vec<String> = manifest
+        .files
+        .keys()
+        .filter(|file| *file != "dataset.json")
+        .cloned()
+        .collect();
+
+    let staging_config = DatasetConfig {
+        name: format!("{}.import-{}", dataset_path, utils::create_random_hash()),
+        ..dataset_config.clone()
+    };
+    let staged = staging_config.save_dataset(&vc_dataset).and_then(|_| {
+        staging_config
+            .backend
+            .import_bundle_files(&staging_config.name, unpack_path, &files)
+    });
+    if let Err(error) = staged {
+        remove_staged_dataset(&staging_config);
+        return Err(error);
+    }
+
+    // Acquire write lock to replace the dataset in the hashmap. Whether the dataset exists is checked again, as it may have been created
+    // or imported concurrently since the bundle was received.
+    let mut datasets_ref = config.datasets.write().unwrap();
+    let exists = config.local_config.contains_key(dataset_path)? || datasets_ref.contains_key(dataset_path);
+    if exists && existing_config.is_none() {
+        remove_staged_dataset(&staging_config);
+        return Err(DaemonError::AlreadyExists);
+    }
+    // A dataset which was removed concurrently does not have to be moved aside anymore.
+    let existing_config = existing_config.filter(|_| exists);
+    if let Err(error) = replace_dataset(&staging_config, &dataset_config, existing_config.as_ref()) {
+        remove_staged_dataset(&staging_config);
+        return Err(error);
+    }
+
+    config.local_config.insert(dataset_path.to_string(), &dataset_config)?;
+    config.pipeline_index.remove_dataset(dataset_path)?;
+    config.pipeline_index.index_dataset(&dataset_config)?;
+    config.promotion_index.index_dataset(&dataset_config)?;
+    config.lineage_index.remove_dataset(dataset_path)?;
+    for pipeline_hash in dataset_config.backend.get_pipeline_executions(dataset_path)? {
+        config
+            .lineage_index
+            .index_pipeline(&dataset_config, &vc_dataset, &pipeline_hash)?;
+    }
+    datasets_ref.insert(dataset_path.to_string(), vc_dataset);
+
+    Ok(dataset_config)
+}
+
+/// Moves a staged dataset to its final name. An existing dataset is first moved aside, and only removed once the staged dataset took its place.
+fn replace_dataset(
+    staging_config: &DatasetConfig,
+    dataset_config: &DatasetConfig,
+    existing_config: Option<&DatasetConfig>,
+) -> Result<(), DaemonError> {
+    let replaced_config = match existing_config {
+        Some(existing_config) => {
+            info!("Overwriting existing dataset {}", dataset_config.name);
+            let replaced_config = DatasetConfig {
+                name: format!("{}.replaced-{}", dataset_config.name, utils::create_random_hash()),
+                ..existing_config.clone()
+            };
+            existing_config
+                .backend
+                .rename_dataset(&existing_config.name, &replaced_config.name)?;
+            Some(replaced_config)
+        }
+        None => None,
+    };
+
+    if let Err(error) = staging_config
+        .backend
+        .rename_dataset(&staging_config.name, &dataset_config.name)
+    {
+        if let (Some(existing_config), Some(replaced_config)) = (existing_config, &replaced_config) {
+            if let Err(restore_error) = existing_config
+                .backend
+                .rename_dataset(&replaced_config.name, &existing_config.name)
+            {
+                error!("Could not restore dataset {}: {}", existing_config.name, restore_error);
+            }
+        }
+        return Err(error);
+    }
+
+    if let Some(replaced_config) = replaced_config {
+        if let Err(error) = replaced_config.remove_dataset() {
+            warn!("Could not remove replaced dataset {}: {}", replaced_config.name, error);
+        }
+    }
+    Ok(())
+}
+
+/// Removes a dataset which was staged for an import that did not complete.
+fn remove_staged_dataset(staging_config: &DatasetConfig) {
+    if let Err(error) = staging_config.remove_dataset() {
+        warn!("Could not remove staged dataset {}: {}", staging_config.name, error);
+    }
+}
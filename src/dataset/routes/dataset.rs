@@ -1,12 +1,14 @@
 //! Routes related to managing branches of a dataset
 
 use crate::config;
+use crate::dataset::history;
+use crate::dataset::models::ForkRequest;
 use crate::dataset::DatasetConfig;
 use crate::error::DaemonError;
 use actix_web::{delete, get, post, web, HttpResponse};
 use iterum_rust::vc;
 use serde_json::json;
-use vc::Dataset;
+use vc::{error::VersionControlError, Dataset};
 
 /// Create a new dataset based on a provided DatasetConfig
 #[post("/")]
@@ -86,6 +88,92 @@ async fn delete_dataset(
     config.datasets.write().unwrap().remove(&dataset_path).unwrap();
     dataset_config.remove_dataset().unwrap();
     config.local_config.remove(&dataset_path)?;
+    config.pipeline_index.remove_dataset(&dataset_path)?;
+    config.lineage_index.remove_dataset(&dataset_path)?;
+    config.promotion_index.remove_dataset(&dataset_path)?;
+    config.retention_log.remove_dataset(&dataset_path)?;
 
     Ok(HttpResponse::Ok().finish())
 }
+
+/// Maps a commit or branch which does not exist to a bad request, as it refers to a part of the request instead of conflicting with the dataset.
+fn unknown_reference(error: VersionControlError) -> DaemonError {
+    match error {
+        VersionControlError::CommitNotFound => DaemonError::BadRequest("Commit does not exist.".to_owned()),
+        VersionControlError::BranchNotFound => DaemonError::BadRequest("Branch does not exist.".to_owned()),
+        error => error.into(),
+    }
+}
+
+/// Fork a dataset at a specific commit or branch. The history of the new dataset ends at the chosen commit, and stored files are shared with the original dataset where the backend allows it.
+#[post("/{dataset}/fork")]
+async fn fork_dataset(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    fork: web::Json<ForkRequest>,
+) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    let fork = fork.into_inner();
+    info!("Forking dataset {} into {}", dataset_path, fork.name);
+
+    let dataset_config: DatasetConfig = config
+        .local_config
+        .get(&dataset_path)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    let vc_dataset: Dataset = config
+        .datasets
+        .read()
+        .unwrap()
+        .get(&dataset_path)
+        .ok_or_else(|| DaemonError::NotFound)?
+        .clone();
+
+    let commit_hash = match (&fork.commit, &fork.branch) {
+        (Some(commit_hash), _) => commit_hash.to_string(),
+        (None, Some(branch)) => history::find_branch(&vc_dataset, branch)
+            .map_err(unknown_reference)?
+            .head
+            .to_string(),
+        (None, None) => {
+            return Err(DaemonError::BadRequest(
+                "Either a commit or a branch should be given.".to_owned(),
+            ))
+        }
+    };
+
+    let forked_vc_dataset = history::replay_history(&vc_dataset, &commit_hash).map_err(unknown_reference)?;
+    let files = history::stored_files(&history::commit_chain(&vc_dataset, &commit_hash).map_err(unknown_reference)?);
+
+    let forked_config = DatasetConfig {
+        name: fork.name,
+        backend: dataset_config.backend.clone(),
+        description: fork
+            .description
+            .unwrap_or_else(|| format!("Fork of {} at commit {}", dataset_path, commit_hash)),
+        retention: dataset_config.retention.clone(),
+    };
+
+    // Acquire write lock before checking the name, so concurrent forks cannot claim the same name.
+    {
+        let mut datasets_ref = config.datasets.write().unwrap();
+        if config.local_config.contains_key(&forked_config.name)? || datasets_ref.contains_key(&forked_config.name) {
+            return Err(DaemonError::AlreadyExists);
+        }
+        forked_config.save_dataset(&forked_vc_dataset)?;
+        if let Err(error) = forked_config.fork_files(&dataset_config, &files) {
+            // Remove the partial fork, as it is not registered and would otherwise stay behind on the storage backend.
+            if let Err(remove_error) = forked_config.remove_dataset() {
+                warn!("Could not remove partial fork {}: {}", forked_config.name, remove_error);
+            }
+            return Err(error);
+        }
+        config
+            .local_config
+            .insert(forked_config.name.to_string(), &forked_config)?;
+        datasets_ref.insert(forked_config.name.to_string(), forked_vc_dataset);
+    }
+
+    Ok(HttpResponse::Ok().json(&forked_config))
+}
@@ -0,0 +1,122 @@
+//! Routes related to importing data which is already present on the server into a new dataset
+
+use crate::config;
+use crate::dataset::history;
+use crate::dataset::models::ImportRequest;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::utils as daemon_utils;
+use actix_web::{post, web, HttpResponse};
+use flate2::read::GzDecoder;
+use iterum_rust::utils;
+use iterum_rust::vc::Dataset;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Environment variable with the directory on the server from which datasets may be imported.
+const IMPORT_ROOT_VARIABLE: &str = "IMPORT_ROOT";
+
+/// Resolves the source of an import, which has to lie within the import root configured by `IMPORT_ROOT`.
+/// Imports are refused altogether when no import root is configured.
+fn resolve_source(source: &str) -> Result<PathBuf, DaemonError> {
+    let import_root = env::var(IMPORT_ROOT_VARIABLE).map_err(|_| {
+        DaemonError::BadRequest(format!("Imports are disabled, as {} is not set.", IMPORT_ROOT_VARIABLE))
+    })?;
+    let import_root = fs::canonicalize(import_root)?;
+    let source = fs::canonicalize(source).map_err(|_| DaemonError::NotFound)?;
+    if !source.starts_with(&import_root) {
+        return Err(DaemonError::BadRequest(format!(
+            "Source {} is not within the import root.",
+            source.display()
+        )));
+    }
+    Ok(source)
+}
+
+/// Unpacks a (possibly gzipped) tarball into the target directory. Tarballs containing links are refused,
+/// as these could refer to files outside of the tarball.
+fn unpack_tarball(tarball: &Path, target: &Path) -> Result<(), DaemonError> {
+    let file = fs::File::open(tarball)?;
+    match tarball.extension().and_then(OsStr::to_str) {
+        Some("gz") | Some("tgz") => daemon_utils::unpack_without_links(GzDecoder::new(file), target),
+        _ => daemon_utils::unpack_without_links(file, target),
+    }
+}
+
+/// Create a new dataset from a directory or tarball on the server. All files are added to the dataset in a single initial commit.
+/// The files are staged in a temporary folder, after which they are stored in the storage backend the same way as files uploaded by the CLI.
+/// Only sources within the directory configured by `IMPORT_ROOT` can be imported.
+#[post("/import")]
+async fn import_dataset(
+    config: web::Data<config::Config>,
+    request: web::Json<ImportRequest>,
+) -> Result<HttpResponse, DaemonError> {
+    let request = request.into_inner();
+    info!("Importing {} as new dataset {}", request.source, request.dataset.name);
+
+    if config.local_config.contains_key(&request.dataset.name)? {
+        return Err(DaemonError::AlreadyExists);
+    }
+    let source = resolve_source(&request.source)?;
+
+    let temp_path = format!("./.tmp/{}/", utils::create_random_hash());
+    fs::create_dir_all(&temp_path).expect("Could not create temporary file directory.");
+    let result = import_from_source(&config, request, &source, Path::new(&temp_path));
+    if let Err(error) = fs::remove_dir_all(&temp_path) {
+        warn!("Could not remove temporary directory {}: {}", temp_path, error);
+    }
+    let dataset_config = result?;
+
+    Ok(HttpResponse::Ok().json(&dataset_config))
+}
+
+/// Imports the source into a new dataset, using the temporary directory to unpack and stage files.
+fn import_from_source(
+    config: &config::Config,
+    request: ImportRequest,
+    source: &Path,
+    temp_path: &Path,
+) -> Result<DatasetConfig, DaemonError> {
+    let dataset_config = request.dataset;
+    let dataset_path = dataset_config.name.to_string();
+
+    let source_dir: PathBuf = if source.is_dir() {
+        source.to_path_buf()
+    } else if source.is_file() {
+        let unpack_dir = temp_path.join("source");
+        unpack_tarball(source, &unpack_dir)?;
+        unpack_dir
+    } else {
+        return Err(DaemonError::NotFound);
+    };
+
+    let files = daemon_utils::list_files_recursively(&source_dir)?;
+    debug!("Importing {} files.", files.len());
+
+    let branch_name = request.branch.unwrap_or_else(|| "master".to_owned());
+    let message = request
+        .message
+        .unwrap_or_else(|| format!("Imported from {}", request.source));
+    let (vc_dataset, commit) = history::commit_files(Dataset::new(), &branch_name, "Initial import", &message, &files)?;
+
+    // Stage the files under the names with which they should be stored in the backend.
+    let staging_path = temp_path.join("staged");
+    for file in &files {
+        let staged_file = staging_path.join(history::stored_file_name(file, &commit.hash));
+        daemon_utils::link_or_copy(&source_dir.join(file), &staged_file)?;
+    }
+
+    // Acquire write lock to add the dataset to the hashmap. The files are stored first, so a saved dataset never refers to missing files.
+    let mut datasets_ref = config.datasets.write().unwrap();
+    if config.local_config.contains_key(&dataset_path)? || datasets_ref.contains_key(&dataset_path) {
+        return Err(DaemonError::AlreadyExists);
+    }
+    dataset_config.store_committed_files(&commit, staging_path.to_string_lossy().into_owned())?;
+    dataset_config.save_dataset(&vc_dataset)?;
+    config.local_config.insert(dataset_path.to_string(), &dataset_config)?;
+    datasets_ref.insert(dataset_path, vc_dataset);
+
+    Ok(dataset_config)
+}
@@ -1,9 +1,12 @@
 //! Routes related to managing branches of a dataset
 
 use crate::config;
+use crate::dataset::gc;
+use crate::dataset::stats;
 use crate::dataset::DatasetConfig;
 use crate::error::DaemonError;
 use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
 use std::ffi::OsStr;
 use std::path::Path;
 
@@ -53,6 +56,8 @@ pub async fn reset_state(config: web::Data<config::Config>) -> Result<HttpRespon
         datasets_ref.remove(&name);
     });
     config.local_config.clear().unwrap();
+    config.pipeline_index.rebuild(&config.local_config)?;
+    config.lineage_index.clear()?;
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -68,3 +73,60 @@ async fn get_vtree(config: web::Data<config::Config>, path: web::Path<String>) -
 
     Ok(HttpResponse::Ok().json(&vc_dataset.version_tree))
 }
+
+/// Query parameters for garbage collection. Garbage collection is a dry run unless stated otherwise.
+#[derive(Deserialize, Debug)]
+pub struct GcOptions {
+    #[serde(default = "default_dry_run")]
+    dry_run: bool,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+/// Removes stored file versions which are no longer reachable from any branch head or pipeline execution. In dry-run mode only reports what would be removed.
+#[post("/{dataset}/gc")]
+async fn collect_garbage(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    options: web::Query<GcOptions>,
+) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    info!(
+        "Collecting garbage for dataset {} (dry run: {})",
+        dataset_path, options.dry_run
+    );
+
+    let dataset_config: DatasetConfig = config
+        .local_config
+        .get(&dataset_path)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    // Acquire write lock, so no files are committed while garbage is being collected.
+    let datasets_ref = config.datasets.write().unwrap();
+    let vc_dataset = datasets_ref.get(&dataset_path).ok_or_else(|| DaemonError::NotFound)?;
+    let report = gc::collect_garbage(&dataset_config, vc_dataset, options.dry_run)?;
+
+    Ok(HttpResponse::Ok().json(&report))
+}
+
+/// Retrieve storage statistics of a dataset: the number of commits and branches, the files at each branch head, the bytes stored per branch, and the bytes used by pipeline executions.
+#[get("/{dataset}/stats")]
+async fn get_stats(config: web::Data<config::Config>, path: web::Path<String>) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    info!("Getting storage statistics of dataset {}", dataset_path);
+
+    let dataset_config: DatasetConfig = config
+        .local_config
+        .get(&dataset_path)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    let datasets = config.datasets.read().unwrap();
+    let vc_dataset = datasets.get(&dataset_path).ok_or_else(|| DaemonError::NotFound)?;
+    let stats = stats::compute_stats(&dataset_config, vc_dataset)?;
+
+    Ok(HttpResponse::Ok().json(&stats))
+}
@@ -1,8 +1,11 @@
 //! The different routes for the data versioning server, used by the CLI to communicate with the daemon.
 mod branch;
+mod bundle;
 mod commit;
 mod dataset;
+mod import;
 mod misc;
+mod retention;
 use actix_web::web;
 
 /// Initializes the different routes, such that Actix exposes the endpoints
@@ -11,6 +14,10 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(dataset::create_dataset);
     cfg.service(dataset::get_dataset);
     cfg.service(dataset::get_datasets);
+    cfg.service(dataset::fork_dataset);
+    cfg.service(import::import_dataset);
+    cfg.service(bundle::export_bundle);
+    cfg.service(bundle::import_bundle);
     cfg.service(branch::get_branch);
     cfg.service(branch::create_branch);
     cfg.service(commit::get_commit);
@@ -18,4 +25,10 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(misc::get_file);
     cfg.service(misc::get_vtree);
     cfg.service(misc::reset_state);
+    cfg.service(misc::collect_garbage);
+    cfg.service(misc::get_stats);
+    cfg.service(retention::get_retention_policy);
+    cfg.service(retention::set_retention_policy);
+    cfg.service(retention::apply_retention_policy);
+    cfg.service(retention::get_retention_log);
 }
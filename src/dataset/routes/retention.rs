@@ -0,0 +1,103 @@
+//! Routes related to the retention policy of a dataset, which determines which pipeline executions of the dataset are deleted automatically.
+use crate::config;
+use crate::dataset::models::RetentionPolicy;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::retention;
+use actix_web::{get, post, put, web, HttpResponse};
+use serde::Deserialize;
+
+/// Query parameters for applying a retention policy.
+#[derive(Deserialize, Debug)]
+pub struct ApplyOptions {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Retrieves the retention policy of a dataset. A dataset without a policy keeps all pipeline executions.
+#[get("/{dataset}/retention")]
+async fn get_retention_policy(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    info!("Getting retention policy of dataset {}", dataset_path);
+
+    let dataset_config: DatasetConfig = config
+        .local_config
+        .get(&dataset_path)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    Ok(HttpResponse::Ok().json(dataset_config.retention.unwrap_or_default()))
+}
+
+/// Sets the retention policy of a dataset. The policy is applied by the background task, or when it is applied explicitly.
+#[put("/{dataset}/retention")]
+async fn set_retention_policy(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    policy: web::Json<RetentionPolicy>,
+) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    let policy = policy.into_inner();
+    info!("Setting retention policy of dataset {} to {:?}", dataset_path, policy);
+
+    retention::validate_policy(&policy)?;
+
+    // Acquire write lock, so the config is not changed concurrently.
+    {
+        let _datasets_ref = config.datasets.write().unwrap();
+        let mut dataset_config: DatasetConfig = config
+            .local_config
+            .get(&dataset_path)?
+            .ok_or_else(|| DaemonError::NotFound)?
+            .into();
+        dataset_config.retention = if policy.keeps_everything() {
+            None
+        } else {
+            Some(policy.clone())
+        };
+        config.local_config.insert(dataset_path, &dataset_config)?;
+    }
+
+    Ok(HttpResponse::Ok().json(&policy))
+}
+
+/// Applies the retention policy of a dataset immediately. With `?dry_run=true`, only reports which pipeline executions would be deleted.
+#[post("/{dataset}/retention/apply")]
+async fn apply_retention_policy(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    options: web::Query<ApplyOptions>,
+) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    info!("Applying retention policy of dataset {}", dataset_path);
+
+    let dataset_config: DatasetConfig = config
+        .local_config
+        .get(&dataset_path)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    let record = retention::apply(&config, &dataset_config, options.dry_run)?;
+
+    Ok(HttpResponse::Ok().json(&record))
+}
+
+/// Retrieves the log of pipeline executions of a dataset which were deleted by its retention policy.
+#[get("/{dataset}/retention/log")]
+async fn get_retention_log(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, DaemonError> {
+    let dataset_path = path.to_string();
+    info!("Getting retention log of dataset {}", dataset_path);
+
+    if !config.local_config.contains_key(&dataset_path)? {
+        return Err(DaemonError::NotFound);
+    }
+    let records = config.retention_log.for_dataset(&dataset_path)?;
+
+    Ok(HttpResponse::Ok().json(&records))
+}
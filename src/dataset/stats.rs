@@ -0,0 +1,100 @@
+//! Contains the computation of storage statistics of a dataset, which are used for capacity planning of the storage backend.
+use crate::dataset::history;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::models::PipelineStorageUsage;
+use iterum_rust::vc::Dataset;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Statistics of a single branch of a dataset.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BranchStats {
+    pub hash: String,
+    pub name: String,
+    pub head: String,
+    /// Number of files present in the head commit of the branch.
+    pub file_count: usize,
+    /// Bytes of all file versions reachable from the head of the branch.
+    pub stored_bytes: u64,
+    /// Bytes of file versions which are only reachable from this branch.
+    pub unique_bytes: u64,
+}
+
+/// Statistics of a dataset as a whole.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DatasetStats {
+    pub commit_count: usize,
+    pub branch_count: usize,
+    pub stored_file_count: usize,
+    pub stored_bytes: u64,
+    pub branches: Vec<BranchStats>,
+    pub pipelines: Vec<PipelineStorageUsage>,
+}
+
+/// Computes the statistics of a dataset, based on its version history and the files stored in the storage backend.
+pub fn compute_stats(dataset_config: &DatasetConfig, vc_dataset: &Dataset) -> Result<DatasetStats, DaemonError> {
+    let stored_files: HashMap<String, u64> = dataset_config
+        .backend
+        .get_stored_files(&dataset_config.name)?
+        .into_iter()
+        .collect();
+    let size_of = |files: &HashSet<String>| -> u64 { files.iter().filter_map(|file| stored_files.get(file)).sum() };
+
+    // Determine which stored files are reachable from each of the branches.
+    let mut branch_files: Vec<(String, HashSet<String>)> = Vec::new();
+    for branch in vc_dataset.branches.values() {
+        let files: HashSet<String> = match vc_dataset.commits.get(&branch.head) {
+            Some(head) => history::stored_files(&history::commit_chain(vc_dataset, &head.hash)?)
+                .into_iter()
+                .collect(),
+            None => HashSet::new(),
+        };
+        branch_files.push((branch.hash.to_string(), files));
+    }
+
+    let mut branches: Vec<BranchStats> = Vec::new();
+    for (branch_hash, files) in &branch_files {
+        let branch = &vc_dataset.branches[branch_hash];
+        let unique_files: HashSet<String> = files
+            .iter()
+            .filter(|file| {
+                branch_files
+                    .iter()
+                    .all(|(other_hash, other_files)| other_hash == branch_hash || !other_files.contains(*file))
+            })
+            .cloned()
+            .collect();
+        branches.push(BranchStats {
+            hash: branch.hash.to_string(),
+            name: branch.name.to_string(),
+            head: branch.head.to_string(),
+            file_count: vc_dataset
+                .commits
+                .get(&branch.head)
+                .map(|commit| commit.files.len())
+                .unwrap_or(0),
+            stored_bytes: size_of(files),
+            unique_bytes: size_of(&unique_files),
+        });
+    }
+
+    let pipeline_hashes = dataset_config.backend.get_pipeline_executions(&dataset_config.name)?;
+    let pipelines = pipeline_hashes
+        .iter()
+        .map(|pipeline_hash| {
+            dataset_config
+                .backend
+                .get_pipeline_storage_usage(&dataset_config.name, pipeline_hash)
+        })
+        .collect::<Result<Vec<PipelineStorageUsage>, DaemonError>>()?;
+
+    Ok(DatasetStats {
+        commit_count: vc_dataset.commits.len(),
+        branch_count: vc_dataset.branches.len(),
+        stored_file_count: stored_files.len(),
+        stored_bytes: stored_files.values().sum(),
+        branches,
+        pipelines,
+    })
+}
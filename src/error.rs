@@ -1,6 +1,7 @@
 //! The various errors that the daemon produces, and the corresponding From<T> functions are in this module.
+use crate::pipeline::models::PipelineStatus;
 use actix_multipart::MultipartError;
-use actix_web::error::ParseError;
+use actix_web::error::{ParseError, PayloadError};
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use iterum_rust::vc;
@@ -16,8 +17,12 @@ pub enum DaemonError {
     Cache(sled::Error),
     MultipartError(MultipartError),
     ParseError(ParseError),
+    PayloadError(PayloadError),
     NotFound,
     AlreadyExists,
+    BadRequest(String),
+    InvalidStatusTransition(PipelineStatus, PipelineStatus),
+    Protected(String),
     VersionControlError(vc::error::VersionControlError),
 }
 
@@ -31,8 +36,14 @@ impl fmt::Display for DaemonError {
             DaemonError::Cache(err) => write!(f, "Cache error: {}", err),
             DaemonError::MultipartError(err) => write!(f, "Multipart error: {}", err),
             DaemonError::ParseError(err) => write!(f, "ParseError: {}", err),
+            DaemonError::PayloadError(err) => write!(f, "Payload error: {}", err),
             DaemonError::NotFound => write!(f, "Resource could not be found."),
             DaemonError::AlreadyExists => write!(f, "Resource already exists."),
+            DaemonError::BadRequest(message) => write!(f, "Bad request: {}", message),
+            DaemonError::InvalidStatusTransition(from, to) => {
+                write!(f, "Pipeline execution cannot move from {} to {}.", from, to)
+            }
+            DaemonError::Protected(reason) => write!(f, "Resource is protected: {}", reason),
             DaemonError::VersionControlError(err) => write!(f, "Version control error: {}", err),
         }
     }
@@ -71,11 +82,21 @@ impl From<ParseError> for DaemonError {
     }
 }
 
+impl From<PayloadError> for DaemonError {
+    fn from(error: PayloadError) -> DaemonError {
+        DaemonError::PayloadError(error)
+    }
+}
+
 impl ResponseError for DaemonError {
     fn error_response(&self) -> HttpResponse {
         let status_code = match self {
             DaemonError::NotFound => StatusCode::NOT_FOUND,
-            DaemonError::VersionControlError(_) | DaemonError::AlreadyExists => StatusCode::CONFLICT,
+            DaemonError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            DaemonError::VersionControlError(_)
+            | DaemonError::AlreadyExists
+            | DaemonError::InvalidStatusTransition(_, _)
+            | DaemonError::Protected(_) => StatusCode::CONFLICT,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -12,9 +12,13 @@ mod backend;
 mod config;
 mod dataset;
 mod error;
+mod openlineage;
 mod pipeline;
+mod utils;
 
 use crate::dataset::DatasetConfig;
+use crate::pipeline::index::{LineageIndex, PipelineIndex, PromotionIndex};
+use crate::pipeline::retention::RetentionLog;
 use iterum_rust::vc::Dataset;
 use std::collections::HashMap;
 use std::sync::RwLock;
@@ -47,12 +51,51 @@ async fn main() -> std::io::Result<()> {
         datasets.insert(dataset_config.name, dataset);
     });
 
+    // Rebuild the index of pipeline executions, so it is in sync with the storage backends
+    let pipeline_index = PipelineIndex::open(&t).expect("Opening pipeline index failed..");
+    let pipeline_count = pipeline_index.rebuild(&t).expect("Rebuilding pipeline index failed..");
+    info!("Indexed {} pipeline executions.", pipeline_count);
+
+    // The lineage index is persistent, so it only has to be built when it does not exist yet or is out of date
+    let lineage_index = LineageIndex::open(&t).expect("Opening lineage index failed..");
+    if lineage_index
+        .is_outdated(&t, &pipeline_index)
+        .expect("Checking lineage index failed..")
+    {
+        info!("Building lineage index.");
+        lineage_index
+            .rebuild(&t, &datasets, &pipeline_index)
+            .expect("Building lineage index failed..");
+    }
+
+    // Rebuild the index of promotions, so it is in sync with the storage backends
+    let promotion_index = PromotionIndex::open(&t).expect("Opening promotion index failed..");
+    let promotion_count = promotion_index
+        .rebuild(&t)
+        .expect("Rebuilding promotion index failed..");
+    info!("Indexed {} promotions.", promotion_count);
+
+    let retention_log = RetentionLog::open(&t).expect("Opening retention log failed..");
+
+    let openlineage = openlineage::OpenLineageEmitter::from_env();
+    if openlineage.is_some() {
+        info!("Emitting OpenLineage events for pipeline executions.");
+    }
+
     // Initialize shared config between actix workers
     let config = web::Data::new(config::Config {
         local_config: t,
         datasets: RwLock::new(datasets),
+        pipeline_index,
+        lineage_index,
+        promotion_index,
+        openlineage,
+        retention_log,
     });
 
+    // Periodically apply the retention policies of datasets
+    pipeline::retention::spawn_retention_task(config.clone());
+
     // Configure actix server
     let mut server = HttpServer::new(move || {
         App::new()
@@ -0,0 +1,211 @@
+//! Contains the translation of pipeline executions into OpenLineage run events, such that external lineage catalogs can pick up pipeline runs of **Iterum**.
+//! Events are either sent to an HTTP endpoint (`OPENLINEAGE_URL`) or appended as NDJSON to a file (`OPENLINEAGE_FILE`). When neither is set, no events are emitted.
+use crate::pipeline::models::PipelineStatus;
+use actix_web::client::Client;
+use chrono::{DateTime, Utc};
+use iterum_rust::pipeline::PipelineExecution;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+
+const PRODUCER: &str = "https://github.com/iterum-provenance/daemon";
+const SCHEMA_URL: &str = "https://openlineage.io/spec/1-0-5/OpenLineage.json#/definitions/RunEvent";
+const VERSION_FACET_SCHEMA_URL: &str = "https://openlineage.io/spec/facets/1-0-0/DatasetVersionDatasetFacet.json";
+
+/// The types of OpenLineage run events.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EventType {
+    Start,
+    Running,
+    Complete,
+    Abort,
+    Fail,
+}
+
+impl EventType {
+    /// The event which corresponds with a pipeline execution moving to a new status, if any.
+    pub fn for_status(status: PipelineStatus) -> Option<EventType> {
+        match status {
+            PipelineStatus::Pending => None,
+            PipelineStatus::Running => Some(EventType::Running),
+            PipelineStatus::Succeeded => Some(EventType::Complete),
+            PipelineStatus::Failed => Some(EventType::Fail),
+            PipelineStatus::Cancelled => Some(EventType::Abort),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Run {
+    run_id: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Job {
+    namespace: String,
+    name: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct LineageDataset {
+    namespace: String,
+    name: String,
+    facets: serde_json::Value,
+}
+
+/// An OpenLineage run event.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RunEvent {
+    event_type: EventType,
+    event_time: DateTime<Utc>,
+    run: Run,
+    job: Job,
+    inputs: Vec<LineageDataset>,
+    outputs: Vec<LineageDataset>,
+    producer: String,
+    #[serde(rename = "schemaURL")]
+    schema_url: String,
+}
+
+/// Where events are delivered to.
+enum Sink {
+    Http(String),
+    File(Mutex<File>),
+}
+
+/// Emits OpenLineage events to the configured sink.
+pub struct OpenLineageEmitter {
+    sink: Sink,
+    namespace: String,
+}
+
+/// OpenLineage requires run ids to be UUIDs, so one is derived from the pipeline hash.
+fn run_id(pipeline_hash: &str) -> String {
+    let digest = Sha256::digest(pipeline_hash.as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    // Mark the UUID as a custom (version 8) UUID with the RFC 4122 variant.
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+impl OpenLineageEmitter {
+    /// Creates an emitter based on the environment. Returns `None` when no sink is configured.
+    pub fn from_env() -> Option<OpenLineageEmitter> {
+        let namespace = env::var("OPENLINEAGE_NAMESPACE").unwrap_or_else(|_| "iterum".to_owned());
+        let sink = if let Ok(url) = env::var("OPENLINEAGE_URL") {
+            Sink::Http(url)
+        } else if let Ok(path) = env::var("OPENLINEAGE_FILE") {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .expect("Could not open OpenLineage event file.");
+            Sink::File(Mutex::new(file))
+        } else {
+            return None;
+        };
+        Some(OpenLineageEmitter { sink, namespace })
+    }
+
+    fn dataset(&self, name: String, version: Option<&str>) -> LineageDataset {
+        let facets = match version {
+            Some(version) => json!({
+                "version": {
+                    "_producer": PRODUCER,
+                    "_schemaURL": VERSION_FACET_SCHEMA_URL,
+                    "datasetVersion": version,
+                }
+            }),
+            None => json!({}),
+        };
+        LineageDataset {
+            namespace: self.namespace.to_string(),
+            name,
+            facets,
+        }
+    }
+
+    /// Creates the event for a pipeline execution. The input is the input commit of the dataset, and the output are the results of the pipeline execution.
+    /// Outputs are only included once the pipeline execution has started.
+    pub fn event(&self, event_type: EventType, pipeline_execution: &PipelineExecution) -> RunEvent {
+        let pipeline_run = &pipeline_execution.pipeline_run;
+        let inputs = vec![self.dataset(
+            pipeline_run.input_dataset.to_string(),
+            Some(&pipeline_run.input_dataset_commit_hash),
+        )];
+        let outputs = match event_type {
+            EventType::Start => Vec::new(),
+            _ => vec![self.dataset(
+                format!("{}/runs/{}", pipeline_run.input_dataset, pipeline_run.pipeline_run_hash),
+                None,
+            )],
+        };
+        RunEvent {
+            event_type,
+            event_time: Utc::now(),
+            run: Run {
+                run_id: run_id(&pipeline_run.pipeline_run_hash),
+            },
+            job: Job {
+                namespace: self.namespace.to_string(),
+                name: pipeline_run.name.to_string(),
+            },
+            inputs,
+            outputs,
+            producer: PRODUCER.to_owned(),
+            schema_url: SCHEMA_URL.to_owned(),
+        }
+    }
+
+    /// Delivers an event to the sink. Failures are logged, but never interrupt the request which caused the event.
+    pub fn emit(&self, event: RunEvent) {
+        debug!(
+            "Emitting OpenLineage {:?} event for job {}",
+            event.event_type, event.job.name
+        );
+        match &self.sink {
+            Sink::Http(url) => {
+                let url = url.to_string();
+                actix_rt::spawn(async move {
+                    match Client::default().post(&url).send_json(&event).await {
+                        Ok(response) if !response.status().is_success() => {
+                            warn!("OpenLineage endpoint responded with {}", response.status())
+                        }
+                        Ok(_) => {}
+                        Err(error) => error!("Could not send OpenLineage event: {}", error),
+                    }
+                });
+            }
+            Sink::File(file) => {
+                let result = serde_json::to_string(&event)
+                    .map_err(std::io::Error::from)
+                    .and_then(|line| writeln!(file.lock().unwrap(), "{}", line));
+                if let Err(error) = result {
+                    error!("Could not write OpenLineage event: {}", error);
+                }
+            }
+        }
+    }
+
+    /// Shortcut to create and deliver the event for a pipeline execution.
+    pub fn emit_for(&self, event_type: EventType, pipeline_execution: &PipelineExecution) {
+        self.emit(self.event(event_type, pipeline_execution));
+    }
+}
@@ -0,0 +1,205 @@
+//! Contains logic to compare two pipeline executions, for example after tweaking the configuration of a transformation step and running the pipeline again.
+use crate::error::DaemonError;
+use crate::pipeline::models::ResultMetadata;
+use iterum_rust::pipeline::PipelineExecution;
+use iterum_rust::provenance::FragmentLineage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// How an item differs between the first and the second pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+    Unchanged,
+}
+
+impl Change {
+    fn between<T: PartialEq>(a: Option<T>, b: Option<T>) -> Change {
+        match (a, b) {
+            (None, Some(_)) => Change::Added,
+            (Some(_), None) => Change::Removed,
+            (a, b) if a == b => Change::Unchanged,
+            _ => Change::Changed,
+        }
+    }
+}
+
+/// The input dataset commits of both pipeline executions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InputComparison {
+    pub dataset_a: String,
+    pub commit_a: String,
+    pub dataset_b: String,
+    pub commit_b: String,
+    pub identical: bool,
+}
+
+/// Differences in the configuration of a transformation step, and in the number of fragments it handled.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StepComparison {
+    pub name: String,
+    pub change: Change,
+    /// Configuration fields of the step which differ between both pipeline executions.
+    pub changed_fields: Vec<String>,
+    pub fragments_a: usize,
+    pub fragments_b: usize,
+}
+
+/// Result files which were added, removed or changed, determined by their content hashes.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ResultComparison {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    pub unchanged: usize,
+}
+
+/// All differences between two pipeline executions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunComparison {
+    pub a: String,
+    pub b: String,
+    pub input: InputComparison,
+    pub steps: Vec<StepComparison>,
+    pub results: ResultComparison,
+}
+
+/// Compares the input commits of two pipeline executions.
+pub fn compare_inputs(a: &PipelineExecution, b: &PipelineExecution) -> InputComparison {
+    let (run_a, run_b) = (&a.pipeline_run, &b.pipeline_run);
+    InputComparison {
+        dataset_a: run_a.input_dataset.to_string(),
+        commit_a: run_a.input_dataset_commit_hash.to_string(),
+        dataset_b: run_b.input_dataset.to_string(),
+        commit_b: run_b.input_dataset_commit_hash.to_string(),
+        identical: run_a.input_dataset == run_b.input_dataset
+            && run_a.input_dataset_commit_hash == run_b.input_dataset_commit_hash,
+    }
+}
+
+fn step_configurations(execution: &PipelineExecution) -> Result<Vec<(String, Value)>, DaemonError> {
+    execution
+        .pipeline_run
+        .steps
+        .iter()
+        .map(|step| -> Result<(String, Value), DaemonError> {
+            Ok((step.name.to_string(), serde_json::to_value(step)?))
+        })
+        .collect()
+}
+
+fn fragment_counts(lineages: &[FragmentLineage]) -> HashMap<&str, usize> {
+    let mut counts = HashMap::new();
+    for lineage in lineages {
+        *counts.entry(lineage.transformation_step.as_str()).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn differing_fields(a: &Value, b: &Value) -> Vec<String> {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => a
+            .keys()
+            .chain(b.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|key| a.get(*key) != b.get(*key))
+            .map(|key| key.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Compares the transformation steps of two pipeline executions. Steps are matched by name, in the order of the first pipeline execution.
+/// Steps which only occur in the lineage, such as the fragmenter, are included as well.
+pub fn compare_steps(
+    a: &PipelineExecution,
+    b: &PipelineExecution,
+    lineages_a: &[FragmentLineage],
+    lineages_b: &[FragmentLineage],
+) -> Result<Vec<StepComparison>, DaemonError> {
+    let steps_a = step_configurations(a)?;
+    let steps_b = step_configurations(b)?;
+    let counts_a = fragment_counts(lineages_a);
+    let counts_b = fragment_counts(lineages_b);
+
+    let mut names: Vec<&str> = Vec::new();
+    for name in steps_a
+        .iter()
+        .chain(steps_b.iter())
+        .map(|(name, _)| name.as_str())
+        .chain(
+            lineages_a
+                .iter()
+                .chain(lineages_b)
+                .map(|lineage| lineage.transformation_step.as_str()),
+        )
+    {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    let find = |steps: &[(String, Value)], name: &str| -> Option<Value> {
+        steps
+            .iter()
+            .find(|(step, _)| step == name)
+            .map(|(_, value)| value.clone())
+    };
+    let comparisons = names
+        .into_iter()
+        .map(|name| {
+            let fragments_a = counts_a.get(name).copied().unwrap_or(0);
+            let fragments_b = counts_b.get(name).copied().unwrap_or(0);
+            let (config_a, config_b) = (find(&steps_a, name), find(&steps_b, name));
+            let (change, changed_fields) = match (&config_a, &config_b) {
+                (Some(config_a), Some(config_b)) => {
+                    let change = if config_a == config_b {
+                        Change::Unchanged
+                    } else {
+                        Change::Changed
+                    };
+                    (change, differing_fields(config_a, config_b))
+                }
+                // Only present in the lineage, so the step is matched on whether it handled any fragments.
+                (None, None) => (
+                    Change::between(
+                        Some(()).filter(|_| fragments_a > 0),
+                        Some(()).filter(|_| fragments_b > 0),
+                    ),
+                    Vec::new(),
+                ),
+                _ => (Change::between(config_a.as_ref(), config_b.as_ref()), Vec::new()),
+            };
+            StepComparison {
+                name: name.to_owned(),
+                change,
+                changed_fields,
+                fragments_a,
+                fragments_b,
+            }
+        })
+        .collect();
+    Ok(comparisons)
+}
+
+/// Compares the results of two pipeline executions by their content hashes.
+pub fn compare_results(a: &BTreeMap<String, ResultMetadata>, b: &BTreeMap<String, ResultMetadata>) -> ResultComparison {
+    let mut comparison = ResultComparison::default();
+    let file_names: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    for file_name in file_names {
+        let hash_a = a.get(file_name).map(|metadata| &metadata.hash);
+        let hash_b = b.get(file_name).map(|metadata| &metadata.hash);
+        match Change::between(hash_a, hash_b) {
+            Change::Added => comparison.added.push(file_name.to_string()),
+            Change::Removed => comparison.removed.push(file_name.to_string()),
+            Change::Changed => comparison.changed.push(file_name.to_string()),
+            Change::Unchanged => comparison.unchanged += 1,
+        }
+    }
+    comparison
+}
@@ -0,0 +1,134 @@
+//! Contains the retrieval of the documents stored next to a pipeline execution, such as its status, annotations, result metadata and promotions,
+//! and the checks which are based on them.
+use crate::config::Config;
+use crate::dataset::models::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::models::{
+    guess_mime_type, Annotations, ExecutionStatus, Promotion, ResultMetadata, ANNOTATIONS_DOCUMENT,
+    PROMOTIONS_DOCUMENT, RESULTS_DOCUMENT, STATUS_DOCUMENT,
+};
+use iterum_rust::vc::Dataset;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Retrieves the status of a pipeline execution. Pipeline executions which were stored without a status are considered to be pending, with an unknown creation time
+pub fn get_execution_status(
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+) -> Result<ExecutionStatus, DaemonError> {
+    match dataset_config
+        .backend
+        .get_pipeline_document(&dataset_config.name, pipeline_hash, STATUS_DOCUMENT)
+    {
+        Ok(status) => Ok(status),
+        Err(DaemonError::NotFound) => Ok(ExecutionStatus::unknown()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Retrieves the annotations of a pipeline execution. Pipeline executions which were never annotated have empty annotations
+pub fn get_annotations(dataset_config: &DatasetConfig, pipeline_hash: &str) -> Result<Annotations, DaemonError> {
+    match dataset_config
+        .backend
+        .get_pipeline_document(&dataset_config.name, pipeline_hash, ANNOTATIONS_DOCUMENT)
+    {
+        Ok(annotations) => Ok(annotations),
+        Err(DaemonError::NotFound) => Ok(Annotations::default()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Retrieves the stored metadata of the results of a pipeline execution
+pub fn get_stored_result_metadata(
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+) -> Result<BTreeMap<String, ResultMetadata>, DaemonError> {
+    match dataset_config
+        .backend
+        .get_pipeline_document(&dataset_config.name, pipeline_hash, RESULTS_DOCUMENT)
+    {
+        Ok(results) => Ok(results),
+        Err(DaemonError::NotFound) => Ok(BTreeMap::new()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Retrieves the metadata of every result of a pipeline execution. Metadata of results which were stored before it was recorded is determined from the stored files.
+pub fn get_result_metadata(
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+) -> Result<BTreeMap<String, ResultMetadata>, DaemonError> {
+    let mut metadata = get_stored_result_metadata(dataset_config, pipeline_hash)?;
+    let results = match dataset_config.get_pipeline_results(pipeline_hash) {
+        Ok(results) => results,
+        Err(DaemonError::NotFound) => Vec::new(),
+        Err(error) => return Err(error),
+    };
+
+    metadata.retain(|file_name, _| results.contains(file_name));
+    for (file_name, file_metadata) in metadata.iter_mut() {
+        if file_metadata.mime_type.is_empty() {
+            file_metadata.mime_type = guess_mime_type(file_name);
+        }
+    }
+    for file_name in results {
+        if !metadata.contains_key(&file_name) {
+            let file_metadata =
+                dataset_config
+                    .backend
+                    .get_pipeline_result_metadata(&dataset_config.name, pipeline_hash, &file_name)?;
+            metadata.insert(file_name, file_metadata);
+        }
+    }
+    Ok(metadata)
+}
+
+/// Lists the reasons to keep a pipeline execution: it is pinned, its results were promoted into a dataset commit,
+/// or the promoted results were consumed by other pipeline executions.
+/// Both deleting a pipeline execution and applying a retention policy refuse to remove pipeline executions for which any reason is listed.
+pub fn protection_reasons(
+    config: &Config,
+    datasets: &HashMap<String, Dataset>,
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+) -> Result<Vec<String>, DaemonError> {
+    let mut blockers = Vec::new();
+    if get_annotations(dataset_config, pipeline_hash)?.pinned {
+        blockers.push(format!("Pipeline execution {} is pinned.", pipeline_hash));
+    }
+
+    let mut consumers = BTreeSet::new();
+    for promotion in get_promotions(dataset_config, pipeline_hash)? {
+        blockers.push(format!(
+            "Results were promoted into commit {} of dataset {}.",
+            promotion.commit, promotion.dataset
+        ));
+        let vc_dataset = match datasets.get(&promotion.dataset) {
+            Some(vc_dataset) => vc_dataset,
+            None => continue,
+        };
+        for file in &promotion.files {
+            for usage in config
+                .lineage_index
+                .find(&promotion.dataset, vc_dataset, &promotion.commit, file)?
+            {
+                consumers.insert(usage.pipeline_hash);
+            }
+        }
+    }
+    for consumer in consumers {
+        blockers.push(format!("Promoted results are used by pipeline execution {}.", consumer));
+    }
+    Ok(blockers)
+}
+
+/// Retrieves the promotions of a pipeline execution. Pipeline executions which were never promoted have none.
+pub fn get_promotions(dataset_config: &DatasetConfig, pipeline_hash: &str) -> Result<Vec<Promotion>, DaemonError> {
+    match dataset_config
+        .backend
+        .get_pipeline_document(&dataset_config.name, pipeline_hash, PROMOTIONS_DOCUMENT)
+    {
+        Ok(promotions) => Ok(promotions),
+        Err(DaemonError::NotFound) => Ok(Vec::new()),
+        Err(error) => Err(error),
+    }
+}
@@ -0,0 +1,490 @@
+//! Contains the persistent indexes over pipeline executions. The indexes are stored in separate trees of the local kv-store.
+//! The pipeline index maps pipeline hashes onto the dataset in which the pipeline execution is stored, such that routes which only know a pipeline hash do not have to search through every dataset.
+//! The lineage index maps files of a dataset onto the fragments of pipeline executions which consumed them, such that the impact of an input file can be determined without reading all lineage.
+//! The promotion index maps commits of a dataset onto the pipeline executions whose results were promoted into them, such that a trace does not have to read the promotions of every pipeline execution.
+use crate::dataset::history;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::documents::get_promotions;
+use crate::pipeline::lineage;
+use crate::pipeline::models::Promotion;
+use iterum_rust::provenance::FragmentLineage;
+use iterum_rust::vc::Dataset;
+use std::collections::{HashMap, HashSet};
+
+/// Name of the tree in the local kv-store which contains the pipeline index.
+const PIPELINE_INDEX_TREE: &str = "pipeline_index";
+
+/// Name of the tree in the local kv-store which contains the lineage index.
+const LINEAGE_INDEX_TREE: &str = "lineage_index";
+
+/// Name of the tree in the local kv-store which contains the keys of the lineage index per pipeline execution, such that the usages of a pipeline execution
+/// can be removed without scanning the whole index.
+const LINEAGE_INDEX_BY_PIPELINE_TREE: &str = "lineage_index_by_pipeline";
+
+/// Name of the tree in the local kv-store which contains the version of the lineage index, and the pipeline executions it contains.
+const LINEAGE_INDEX_META_TREE: &str = "lineage_index_meta";
+
+/// Key in the meta tree under which the version of the lineage index is stored.
+const LINEAGE_INDEX_VERSION_KEY: &str = "version";
+
+/// Prefix of keys in the meta tree which mark a pipeline execution as indexed.
+const INDEXED_PIPELINE_PREFIX: &str = "pipeline/";
+
+/// Name of the tree in the local kv-store which contains the promotion index.
+const PROMOTION_INDEX_TREE: &str = "promotion_index";
+
+/// Version of the layout of the lineage index, which is increased whenever keys are derived differently.
+/// An index with another version is rebuilt on startup.
+const LINEAGE_INDEX_VERSION: &str = "3";
+
+/// Separator between the parts of keys in the lineage index.
+const KEY_SEPARATOR: char = '\0';
+
+/// Index from pipeline hash to dataset name.
+pub struct PipelineIndex {
+    tree: sled::Tree,
+}
+
+impl PipelineIndex {
+    /// Opens the index in the local kv-store, creating it if it does not exist yet.
+    pub fn open(db: &sled::Db) -> Result<PipelineIndex, DaemonError> {
+        Ok(PipelineIndex {
+            tree: db.open_tree(PIPELINE_INDEX_TREE)?,
+        })
+    }
+
+    /// Registers a pipeline execution as belonging to a dataset.
+    pub fn insert(&self, pipeline_hash: &str, dataset_name: &str) -> Result<(), DaemonError> {
+        self.tree.insert(pipeline_hash, dataset_name)?;
+        Ok(())
+    }
+
+    /// Removes a pipeline execution from the index.
+    pub fn remove(&self, pipeline_hash: &str) -> Result<(), DaemonError> {
+        self.tree.remove(pipeline_hash)?;
+        Ok(())
+    }
+
+    /// Retrieves the name of the dataset a pipeline execution belongs to.
+    pub fn get(&self, pipeline_hash: &str) -> Result<Option<String>, DaemonError> {
+        Ok(self
+            .tree
+            .get(pipeline_hash)?
+            .map(|ivec| String::from_utf8(ivec.to_vec()).expect("Converting bytes to string failed.")))
+    }
+
+    /// Retrieves all pipeline hashes in the index, together with the name of their dataset.
+    pub fn all(&self) -> Result<Vec<(String, String)>, DaemonError> {
+        self.tree
+            .iter()
+            .map(|kv| -> Result<(String, String), DaemonError> {
+                let (key, value) = kv?;
+                let pipeline_hash = String::from_utf8(key.to_vec()).expect("Converting bytes to string failed.");
+                let dataset_name = String::from_utf8(value.to_vec()).expect("Converting bytes to string failed.");
+                Ok((pipeline_hash, dataset_name))
+            })
+            .collect()
+    }
+
+    /// Adds all pipeline executions which are stored for a dataset to the index.
+    pub fn index_dataset(&self, dataset_config: &DatasetConfig) -> Result<usize, DaemonError> {
+        let pipeline_hashes = dataset_config.backend.get_pipeline_executions(&dataset_config.name)?;
+        for pipeline_hash in &pipeline_hashes {
+            self.insert(pipeline_hash, &dataset_config.name)?;
+        }
+        Ok(pipeline_hashes.len())
+    }
+
+    /// Removes all pipeline executions of a dataset from the index.
+    pub fn remove_dataset(&self, dataset_name: &str) -> Result<(), DaemonError> {
+        for (pipeline_hash, indexed_dataset) in self.all()? {
+            if indexed_dataset == dataset_name {
+                self.remove(&pipeline_hash)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the index from scratch, by retrieving the pipeline executions of each dataset in the local kv-store.
+    pub fn rebuild(&self, db: &sled::Db) -> Result<usize, DaemonError> {
+        self.tree.clear()?;
+        let mut count = 0;
+        for kv in db.iter() {
+            let (_key, value) = kv?;
+            let dataset_config: DatasetConfig = value.into();
+            count += self.index_dataset(&dataset_config)?;
+        }
+        Ok(count)
+    }
+}
+
+/// A fragment of a pipeline execution which consumed a file of a dataset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputUsage {
+    pub pipeline_hash: String,
+    pub fragment_id: String,
+}
+
+/// Index from a version of a file of a dataset to the fragments which consumed it.
+/// Each usage is stored as a separate key of the form `dataset/commit/file/pipeline/fragment`, so the usages of a file can be found with a prefix scan.
+/// The commit in the key is the commit which stored the version of the file, such that a file is found regardless of the commit it was read from.
+/// Every key is also stored prefixed with its pipeline hash in a separate tree, from which the usages of a single pipeline execution are found.
+pub struct LineageIndex {
+    tree: sled::Tree,
+    by_pipeline: sled::Tree,
+    meta: sled::Tree,
+}
+
+impl LineageIndex {
+    /// Opens the index in the local kv-store, creating it if it does not exist yet.
+    pub fn open(db: &sled::Db) -> Result<LineageIndex, DaemonError> {
+        Ok(LineageIndex {
+            tree: db.open_tree(LINEAGE_INDEX_TREE)?,
+            by_pipeline: db.open_tree(LINEAGE_INDEX_BY_PIPELINE_TREE)?,
+            meta: db.open_tree(LINEAGE_INDEX_META_TREE)?,
+        })
+    }
+
+    /// Resolves a file at a commit to the commit which stored that version of the file. Files which cannot be resolved are kept at the given commit.
+    fn resolve_version(vc_dataset: &Dataset, commit_hash: &str, file: &str) -> String {
+        match history::file_version(vc_dataset, commit_hash, file) {
+            Ok(Some(version)) => version.to_owned(),
+            _ => commit_hash.to_owned(),
+        }
+    }
+
+    fn mark_indexed(&self, pipeline_hash: &str) -> Result<(), DaemonError> {
+        self.meta.insert(
+            format!("{}{}", INDEXED_PIPELINE_PREFIX, pipeline_hash),
+            Vec::<u8>::new(),
+        )?;
+        Ok(())
+    }
+
+    fn indexed_pipelines(&self) -> Result<HashSet<String>, DaemonError> {
+        self.meta
+            .scan_prefix(INDEXED_PIPELINE_PREFIX)
+            .keys()
+            .map(|key| -> Result<String, DaemonError> {
+                let key = String::from_utf8(key?.to_vec()).expect("Converting bytes to string failed.");
+                Ok(key[INDEXED_PIPELINE_PREFIX.len()..].to_owned())
+            })
+            .collect()
+    }
+
+    /// Whether the index has to be rebuilt: when it has another version, contains pipeline executions which no longer exist,
+    /// or misses pipeline executions of which lineage is stored.
+    pub fn is_outdated(&self, db: &sled::Db, pipeline_index: &PipelineIndex) -> Result<bool, DaemonError> {
+        if self.meta.get(LINEAGE_INDEX_VERSION_KEY)?.as_deref() != Some(LINEAGE_INDEX_VERSION.as_bytes()) {
+            return Ok(true);
+        }
+        let mut indexed = self.indexed_pipelines()?;
+        for (pipeline_hash, dataset_name) in pipeline_index.all()? {
+            if indexed.remove(&pipeline_hash) {
+                continue;
+            }
+            if let Some(value) = db.get(&dataset_name)? {
+                let dataset_config: DatasetConfig = value.into();
+                if !lineage::load_lineages(&dataset_config, &pipeline_hash)?.is_empty() {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(!indexed.is_empty())
+    }
+
+    fn key(parts: &[&str]) -> String {
+        parts.iter().fold(String::new(), |mut key, part| {
+            key.push_str(part);
+            key.push(KEY_SEPARATOR);
+            key
+        })
+    }
+
+    fn parse_key(key: &[u8]) -> Vec<String> {
+        String::from_utf8(key.to_vec())
+            .expect("Converting bytes to string failed.")
+            .split(KEY_SEPARATOR)
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Adds the input files of a fragment, read from a commit of a dataset, to the index. Only fragments created by the fragmenter consume files of the dataset directly,
+    /// other fragments are ignored.
+    pub fn insert(
+        &self,
+        dataset_name: &str,
+        vc_dataset: &Dataset,
+        commit_hash: &str,
+        pipeline_hash: &str,
+        fragment: &FragmentLineage,
+    ) -> Result<(), DaemonError> {
+        self.mark_indexed(pipeline_hash)?;
+        let metadata = &fragment.description.metadata;
+        if !metadata.predecessors.is_empty() {
+            return Ok(());
+        }
+        for file in &fragment.description.files {
+            let key = LineageIndex::key(&[
+                dataset_name,
+                &LineageIndex::resolve_version(vc_dataset, commit_hash, &file.name),
+                &file.name,
+                pipeline_hash,
+                &metadata.fragment_id,
+            ]);
+            self.by_pipeline
+                .insert(LineageIndex::pipeline_key(pipeline_hash, &key), Vec::<u8>::new())?;
+            self.tree.insert(key, Vec::<u8>::new())?;
+        }
+        Ok(())
+    }
+
+    /// Key under which a key of the index is stored for its pipeline execution.
+    fn pipeline_key(pipeline_hash: &str, key: &str) -> String {
+        format!("{}{}", LineageIndex::key(&[pipeline_hash]), key)
+    }
+
+    /// Finds the fragments which consumed a file at a specific commit of a dataset. Fragments which read the same version of the file from another commit are found as well.
+    pub fn find(
+        &self,
+        dataset_name: &str,
+        vc_dataset: &Dataset,
+        commit_hash: &str,
+        file: &str,
+    ) -> Result<Vec<InputUsage>, DaemonError> {
+        let version = LineageIndex::resolve_version(vc_dataset, commit_hash, file);
+        let prefix = LineageIndex::key(&[dataset_name, &version, file]);
+        self.tree
+            .scan_prefix(prefix)
+            .keys()
+            .map(|key| -> Result<InputUsage, DaemonError> {
+                let parts = LineageIndex::parse_key(&key?);
+                Ok(InputUsage {
+                    pipeline_hash: parts[3].to_string(),
+                    fragment_id: parts[4].to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Removes all usages by a pipeline execution from the index.
+    pub fn remove_pipeline(&self, pipeline_hash: &str) -> Result<(), DaemonError> {
+        self.meta
+            .remove(format!("{}{}", INDEXED_PIPELINE_PREFIX, pipeline_hash))?;
+        let prefix = LineageIndex::key(&[pipeline_hash]);
+        for pipeline_key in self.by_pipeline.scan_prefix(&prefix).keys() {
+            let pipeline_key = pipeline_key?;
+            self.tree.remove(&pipeline_key[prefix.len()..])?;
+            self.by_pipeline.remove(pipeline_key)?;
+        }
+        Ok(())
+    }
+
+    /// Removes all usages of files of a dataset from the index.
+    pub fn remove_dataset(&self, dataset_name: &str) -> Result<(), DaemonError> {
+        let mut pipeline_hashes = HashSet::new();
+        for key in self.tree.scan_prefix(LineageIndex::key(&[dataset_name])).keys() {
+            let key = key?;
+            let pipeline_hash = LineageIndex::parse_key(&key)[3].to_string();
+            let key_string = String::from_utf8(key.to_vec()).expect("Converting bytes to string failed.");
+            self.by_pipeline
+                .remove(LineageIndex::pipeline_key(&pipeline_hash, &key_string))?;
+            self.tree.remove(key)?;
+            pipeline_hashes.insert(pipeline_hash);
+        }
+        for pipeline_hash in pipeline_hashes {
+            self.meta
+                .remove(format!("{}{}", INDEXED_PIPELINE_PREFIX, pipeline_hash))?;
+        }
+        Ok(())
+    }
+
+    /// Removes all usages from the index.
+    pub fn clear(&self) -> Result<(), DaemonError> {
+        self.tree.clear()?;
+        self.by_pipeline.clear()?;
+        self.meta.clear()?;
+        self.meta.insert(LINEAGE_INDEX_VERSION_KEY, LINEAGE_INDEX_VERSION)?;
+        Ok(())
+    }
+
+    /// Adds the stored lineage of a pipeline execution to the index. Pipeline executions without a stored execution are skipped, as their input commit is unknown.
+    pub fn index_pipeline(
+        &self,
+        dataset_config: &DatasetConfig,
+        vc_dataset: &Dataset,
+        pipeline_hash: &str,
+    ) -> Result<(), DaemonError> {
+        let pipeline_execution = match dataset_config
+            .backend
+            .get_pipeline_execution(&dataset_config.name, pipeline_hash)
+        {
+            Ok(pipeline_execution) => pipeline_execution,
+            Err(DaemonError::NotFound) => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let commit_hash = &pipeline_execution.pipeline_run.input_dataset_commit_hash;
+        self.mark_indexed(pipeline_hash)?;
+        for fragment in lineage::load_lineages(dataset_config, pipeline_hash)? {
+            self.insert(&dataset_config.name, vc_dataset, commit_hash, pipeline_hash, &fragment)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the index from scratch, using the stored lineage of all pipeline executions in the pipeline index.
+    pub fn rebuild(
+        &self,
+        db: &sled::Db,
+        datasets: &HashMap<String, Dataset>,
+        pipeline_index: &PipelineIndex,
+    ) -> Result<(), DaemonError> {
+        self.clear()?;
+        for (pipeline_hash, dataset_name) in pipeline_index.all()? {
+            if let (Some(value), Some(vc_dataset)) = (db.get(&dataset_name)?, datasets.get(&dataset_name)) {
+                let dataset_config: DatasetConfig = value.into();
+                self.index_pipeline(&dataset_config, vc_dataset, &pipeline_hash)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Index from a commit of a dataset to the pipeline execution whose results were promoted into it.
+/// Each promotion is stored under a key of the form `dataset/commit`, so the promotions into a dataset can be removed with a prefix scan.
+pub struct PromotionIndex {
+    tree: sled::Tree,
+}
+
+impl PromotionIndex {
+    /// Opens the index in the local kv-store, creating it if it does not exist yet.
+    pub fn open(db: &sled::Db) -> Result<PromotionIndex, DaemonError> {
+        Ok(PromotionIndex {
+            tree: db.open_tree(PROMOTION_INDEX_TREE)?,
+        })
+    }
+
+    /// Registers the commit of a promotion as containing results of its pipeline execution.
+    pub fn insert(&self, promotion: &Promotion) -> Result<(), DaemonError> {
+        self.tree.insert(
+            LineageIndex::key(&[&promotion.dataset, &promotion.commit]),
+            promotion.pipeline_hash.as_str(),
+        )?;
+        Ok(())
+    }
+
+    /// Retrieves the pipeline execution whose results were promoted into a commit of a dataset.
+    pub fn get(&self, dataset_name: &str, commit_hash: &str) -> Result<Option<String>, DaemonError> {
+        Ok(self
+            .tree
+            .get(LineageIndex::key(&[dataset_name, commit_hash]))?
+            .map(|ivec| String::from_utf8(ivec.to_vec()).expect("Converting bytes to string failed.")))
+    }
+
+    /// Removes the promotions of a pipeline execution from the index.
+    pub fn remove_promotions(&self, promotions: &[Promotion]) -> Result<(), DaemonError> {
+        for promotion in promotions {
+            self.tree
+                .remove(LineageIndex::key(&[&promotion.dataset, &promotion.commit]))?;
+        }
+        Ok(())
+    }
+
+    /// Removes all promotions into a dataset from the index.
+    pub fn remove_dataset(&self, dataset_name: &str) -> Result<(), DaemonError> {
+        for key in self.tree.scan_prefix(LineageIndex::key(&[dataset_name])).keys() {
+            self.tree.remove(key?)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the promotions of all pipeline executions which are stored for a dataset to the index.
+    pub fn index_dataset(&self, dataset_config: &DatasetConfig) -> Result<usize, DaemonError> {
+        let mut count = 0;
+        for pipeline_hash in dataset_config.backend.get_pipeline_executions(&dataset_config.name)? {
+            for promotion in get_promotions(dataset_config, &pipeline_hash)? {
+                self.insert(&promotion)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Rebuilds the index from scratch, by retrieving the promotions of the pipeline executions of each dataset in the local kv-store.
+    pub fn rebuild(&self, db: &sled::Db) -> Result<usize, DaemonError> {
+        self.tree.clear()?;
+        let mut count = 0;
+        for kv in db.iter() {
+            let (_key, value) = kv?;
+            let dataset_config: DatasetConfig = value.into();
+            count += self.index_dataset(&dataset_config)?;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::lineage::tests::fragment_lineage;
+
+    #[test]
+    fn usages_are_removed_per_pipeline_and_dataset() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let index = LineageIndex::open(&db).unwrap();
+        let files = vec!["a.csv".to_owned()];
+        let (vc_dataset, commit) = history::commit_files(Dataset::new(), "master", "input", "", &files).unwrap();
+        let fragment = fragment_lineage("f1", "fragmenter", &[], &["a.csv"]);
+        for (dataset_name, pipeline_hash) in &[("dataset", "run1"), ("dataset", "run2"), ("other", "run3")] {
+            index
+                .insert(dataset_name, &vc_dataset, &commit.hash, pipeline_hash, &fragment)
+                .unwrap();
+        }
+        let consumers = |dataset_name: &str| -> Vec<String> {
+            index
+                .find(dataset_name, &vc_dataset, &commit.hash, "a.csv")
+                .unwrap()
+                .into_iter()
+                .map(|usage| usage.pipeline_hash)
+                .collect()
+        };
+        assert_eq!(consumers("dataset"), vec!["run1", "run2"]);
+
+        index.remove_pipeline("run1").unwrap();
+        assert_eq!(consumers("dataset"), vec!["run2"]);
+        assert_eq!(consumers("other"), vec!["run3"]);
+
+        index.remove_dataset("dataset").unwrap();
+        assert!(consumers("dataset").is_empty());
+        assert_eq!(consumers("other"), vec!["run3"]);
+        assert_eq!(index.by_pipeline.len(), 1);
+        assert_eq!(index.indexed_pipelines().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn promotions_are_found_per_dataset_and_commit() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let index = PromotionIndex::open(&db).unwrap();
+        let promotion = |pipeline_hash: &str, dataset: &str, commit: &str| Promotion {
+            pipeline_hash: pipeline_hash.to_owned(),
+            dataset: dataset.to_owned(),
+            branch: "master".to_owned(),
+            commit: commit.to_owned(),
+            files: vec!["a.csv".to_owned()],
+            timestamp: chrono::Utc::now(),
+        };
+        let promotions = vec![promotion("run1", "dataset", "c1"), promotion("run1", "other", "c2")];
+        for promotion in promotions.iter().chain(&[promotion("run2", "dataset", "c3")]) {
+            index.insert(promotion).unwrap();
+        }
+        assert_eq!(index.get("dataset", "c1").unwrap().as_deref(), Some("run1"));
+        assert_eq!(index.get("other", "c1").unwrap(), None);
+
+        index.remove_promotions(&promotions).unwrap();
+        assert_eq!(index.get("dataset", "c1").unwrap(), None);
+        assert_eq!(index.get("dataset", "c3").unwrap().as_deref(), Some("run2"));
+
+        index.remove_dataset("dataset").unwrap();
+        assert_eq!(index.get("dataset", "c3").unwrap(), None);
+    }
+}
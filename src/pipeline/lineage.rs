@@ -0,0 +1,428 @@
+//! Contains the assembly of the stored FragmentLineages of a pipeline execution into a single provenance graph.
+//! Fragments without predecessors are created by the fragmenter from files of the input dataset. Every other fragment is produced by a transformation step, from its predecessor fragments.
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::models::ResultMetadata;
+use iterum_rust::provenance::FragmentLineage;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// The different kinds of nodes in a lineage graph.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    InputFile,
+    Fragment,
+    Step,
+    OutputFile,
+}
+
+/// The different kinds of edges in a lineage graph.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    /// An input file is part of a fragment created by the fragmenter.
+    PartOf,
+    /// A fragment is used to derive another fragment.
+    DerivedInto,
+    /// A transformation step produced a fragment.
+    Produced,
+    /// A fragment contains an output file.
+    Contains,
+}
+
+/// A node in a lineage graph. The id is prefixed with the kind of node, as the same name can be used for a file and a fragment.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Node {
+    pub id: String,
+    pub kind: NodeKind,
+    pub label: String,
+}
+
+/// A directed edge in a lineage graph, following the flow of data.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub kind: EdgeKind,
+}
+
+/// Directed acyclic graph of the provenance of a pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LineageGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Result of tracing a result file back to the input files it was derived from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackwardTrace {
+    pub result: String,
+    pub dataset: String,
+    pub commit: String,
+    /// Pipeline execution from which the input commit, or its closest promoted ancestor, was promoted. The trace can be continued through it.
+    pub promoted_from: Option<String>,
+    pub inputs: Vec<String>,
+    pub fragments: Vec<String>,
+    pub steps: Vec<String>,
+}
+
+/// Impact of an input file on a single pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunImpact {
+    pub pipeline_hash: String,
+    pub fragments: Vec<String>,
+    /// Results of the pipeline execution which were derived from the file.
+    pub results: Vec<String>,
+}
+
+/// All pipeline executions, fragments and result files which depend on a file of a dataset.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImpactReport {
+    pub dataset: String,
+    pub commit: String,
+    pub file: String,
+    pub runs: Vec<RunImpact>,
+}
+
+/// Identifiers of the nodes in a lineage graph. Files are scoped by where they come from: input files by the commit they were read from,
+/// and output files by the fragment which produced them, as different files can have the same name.
+pub fn input_file_node_id(commit_hash: &str, file: &str) -> String {
+    format!("file:{}/{}", commit_hash, file)
+}
+
+pub fn output_file_node_id(fragment_id: &str, file: &str) -> String {
+    format!("file:{}/{}", fragment_id, file)
+}
+
+pub fn fragment_node_id(fragment_id: &str) -> String {
+    format!("fragment:{}", fragment_id)
+}
+
+pub fn step_node_id(step: &str) -> String {
+    format!("step:{}", step)
+}
+
+/// Retrieves all FragmentLineages which are stored for a pipeline execution.
+pub fn load_lineages(dataset_config: &DatasetConfig, pipeline_hash: &str) -> Result<Vec<FragmentLineage>, DaemonError> {
+    match dataset_config
+        .backend
+        .get_all_pipeline_fragment_lineages(dataset_config, pipeline_hash)
+    {
+        Ok(lineages) => Ok(lineages),
+        Err(DaemonError::NotFound) => Ok(Vec::new()),
+        Err(error) => Err(error),
+    }
+}
+
+impl LineageGraph {
+    fn add_node(&mut self, seen: &mut HashSet<String>, id: String, kind: NodeKind, label: &str) {
+        if seen.insert(id.to_string()) {
+            self.nodes.push(Node {
+                id,
+                kind,
+                label: label.to_owned(),
+            });
+        }
+    }
+
+    fn add_edge(&mut self, from: String, to: String, kind: EdgeKind) {
+        self.edges.push(Edge { from, to, kind });
+    }
+
+    /// Assembles the graph from the FragmentLineages of a pipeline execution, which read its input files from `input_commit`.
+    /// Predecessors of which no lineage is stored are left out, such that every edge connects two nodes of the graph.
+    pub fn build(input_commit: &str, lineages: &[FragmentLineage]) -> LineageGraph {
+        let mut graph = LineageGraph::default();
+        let mut seen: HashSet<String> = HashSet::new();
+        let known_fragments: HashSet<&str> = lineages
+            .iter()
+            .map(|lineage| lineage.description.metadata.fragment_id.as_str())
+            .collect();
+
+        for lineage in lineages {
+            let metadata = &lineage.description.metadata;
+            let fragment_id = fragment_node_id(&metadata.fragment_id);
+            graph.add_node(
+                &mut seen,
+                fragment_id.to_string(),
+                NodeKind::Fragment,
+                &metadata.fragment_id,
+            );
+
+            let step_id = step_node_id(&lineage.transformation_step);
+            graph.add_node(
+                &mut seen,
+                step_id.to_string(),
+                NodeKind::Step,
+                &lineage.transformation_step,
+            );
+            graph.add_edge(step_id, fragment_id.to_string(), EdgeKind::Produced);
+
+            for predecessor in &metadata.predecessors {
+                if !known_fragments.contains(predecessor.as_str()) {
+                    debug!("Lineage of fragment {} is missing.", predecessor);
+                    continue;
+                }
+                graph.add_edge(
+                    fragment_node_id(predecessor),
+                    fragment_id.to_string(),
+                    EdgeKind::DerivedInto,
+                );
+            }
+
+            let is_input = metadata.predecessors.is_empty();
+            for file in &lineage.description.files {
+                if is_input {
+                    let file_id = input_file_node_id(input_commit, &file.name);
+                    graph.add_node(&mut seen, file_id.to_string(), NodeKind::InputFile, &file.name);
+                    graph.add_edge(file_id, fragment_id.to_string(), EdgeKind::PartOf);
+                } else {
+                    let file_id = output_file_node_id(&metadata.fragment_id, &file.name);
+                    graph.add_node(&mut seen, file_id.to_string(), NodeKind::OutputFile, &file.name);
+                    graph.add_edge(fragment_id.to_string(), file_id, EdgeKind::Contains);
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Renders the graph in the Graphviz DOT language.
+    pub fn to_dot(&self) -> String {
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut dot = String::from("digraph lineage {\n    rankdir=LR;\n");
+        for node in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::InputFile | NodeKind::OutputFile => "note",
+                NodeKind::Fragment => "ellipse",
+                NodeKind::Step => "box",
+            };
+            dot.push_str(&format!(
+                "    \"{}\" [label=\"{}\", shape={}];\n",
+                escape(&node.id),
+                escape(&node.label),
+                shape
+            ));
+        }
+        for edge in &self.edges {
+            let label = serde_json::to_value(edge.kind)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_owned))
+                .unwrap_or_default();
+            dot.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}\"];\n",
+                escape(&edge.from),
+                escape(&edge.to),
+                label
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Walks back from the fragments which contain `file` through their predecessors, up to the fragments created by the fragmenter.
+/// Returns the input files, the fragments and the transformation steps that contributed to the file, or `None` if no fragment contains the file.
+pub fn trace_backward(lineages: &[FragmentLineage], file: &str) -> Option<(Vec<String>, Vec<String>, Vec<String>)> {
+    let fragments: HashMap<&str, &FragmentLineage> = lineages
+        .iter()
+        .map(|lineage| (lineage.description.metadata.fragment_id.as_str(), lineage))
+        .collect();
+
+    let mut queue: Vec<&FragmentLineage> = lineages
+        .iter()
+        .filter(|lineage| !lineage.description.metadata.predecessors.is_empty())
+        .filter(|lineage| lineage.description.files.iter().any(|desc| desc.name == file))
+        .collect();
+    if queue.is_empty() {
+        return None;
+    }
+
+    let mut visited: BTreeSet<String> = BTreeSet::new();
+    let mut inputs: BTreeSet<String> = BTreeSet::new();
+    let mut steps: BTreeSet<String> = BTreeSet::new();
+    while let Some(lineage) = queue.pop() {
+        let metadata = &lineage.description.metadata;
+        if !visited.insert(metadata.fragment_id.to_string()) {
+            continue;
+        }
+        steps.insert(lineage.transformation_step.to_string());
+        if metadata.predecessors.is_empty() {
+            inputs.extend(lineage.description.files.iter().map(|desc| desc.name.to_string()));
+        }
+        for predecessor in &metadata.predecessors {
+            match fragments.get(predecessor.as_str()) {
+                Some(predecessor) => queue.push(*predecessor),
+                None => warn!("Lineage of fragment {} is missing.", predecessor),
+            }
+        }
+    }
+
+    Some((
+        inputs.into_iter().collect(),
+        visited.into_iter().collect(),
+        steps.into_iter().collect(),
+    ))
+}
+
+/// Walks forward from the given fragments to every fragment derived from them.
+/// Returns all fragments reached (including the given ones), and the files of the derived fragments, which include the files of intermediate fragments.
+pub fn trace_forward(lineages: &[FragmentLineage], fragment_ids: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut successors: HashMap<&str, Vec<&FragmentLineage>> = HashMap::new();
+    for lineage in lineages {
+        for predecessor in &lineage.description.metadata.predecessors {
+            successors.entry(predecessor.as_str()).or_default().push(lineage);
+        }
+    }
+
+    let mut visited: BTreeSet<String> = BTreeSet::new();
+    let mut outputs: BTreeSet<String> = BTreeSet::new();
+    let mut queue: Vec<&str> = fragment_ids.iter().map(String::as_str).collect();
+    while let Some(fragment_id) = queue.pop() {
+        if !visited.insert(fragment_id.to_owned()) {
+            continue;
+        }
+        for successor in successors.get(fragment_id).into_iter().flatten() {
+            outputs.extend(successor.description.files.iter().map(|desc| desc.name.to_string()));
+            queue.push(&successor.description.metadata.fragment_id);
+        }
+    }
+
+    (visited.into_iter().collect(), outputs.into_iter().collect())
+}
+
+/// Selects the results of a pipeline execution which were derived from the given fragments. Results which record the fragment that produced them
+/// are selected on that fragment, other results on whether one of the derived fragments contains a file with the same name.
+pub fn derived_results(
+    results: &BTreeMap<String, ResultMetadata>,
+    fragments: &[String],
+    derived_files: &[String],
+) -> Vec<String> {
+    results
+        .iter()
+        .filter(|(name, metadata)| match &metadata.fragment {
+            Some(fragment) => fragments.contains(fragment),
+            None => derived_files.contains(name),
+        })
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Creates the lineage of a fragment with the given predecessors and files. Also used by the tests of other modules.
+    pub fn fragment_lineage(fragment_id: &str, step: &str, predecessors: &[&str], files: &[&str]) -> FragmentLineage {
+        serde_json::from_value(json!({
+            "transformation_step": step,
+            "description": {
+                "files": files.iter().map(|name| json!({ "name": name, "bucket": "" })).collect::<Vec<_>>(),
+                "metadata": {
+                    "fragment_id": fragment_id,
+                    "predecessors": predecessors,
+                    "custom": null,
+                },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn has_edge(graph: &LineageGraph, from: &str, to: &str, kind: EdgeKind) -> bool {
+        graph
+            .edges
+            .iter()
+            .any(|edge| edge.from == from && edge.to == to && edge.kind == kind)
+    }
+
+    #[test]
+    fn files_with_the_same_name_are_separate_nodes() {
+        let lineages = vec![
+            fragment_lineage("f1", "fragmenter", &[], &["data.csv"]),
+            fragment_lineage("f2", "clean", &["f1"], &["data.csv"]),
+            fragment_lineage("f3", "clean", &["f1"], &["data.csv"]),
+        ];
+        let graph = LineageGraph::build("c1", &lineages);
+
+        let file_nodes: Vec<&Node> = graph
+            .nodes
+            .iter()
+            .filter(|node| node.kind == NodeKind::InputFile || node.kind == NodeKind::OutputFile)
+            .collect();
+        assert_eq!(file_nodes.len(), 3);
+        assert!(has_edge(
+            &graph,
+            &input_file_node_id("c1", "data.csv"),
+            &fragment_node_id("f1"),
+            EdgeKind::PartOf
+        ));
+        assert!(has_edge(
+            &graph,
+            &fragment_node_id("f2"),
+            &output_file_node_id("f2", "data.csv"),
+            EdgeKind::Contains
+        ));
+        assert!(has_edge(
+            &graph,
+            &fragment_node_id("f1"),
+            &fragment_node_id("f3"),
+            EdgeKind::DerivedInto
+        ));
+    }
+
+    #[test]
+    fn edges_only_connect_nodes_of_the_graph() {
+        let lineages = vec![
+            fragment_lineage("f1", "fragmenter", &[], &["a.csv"]),
+            fragment_lineage("f2", "clean", &["f1", "missing"], &["b.csv"]),
+        ];
+        let graph = LineageGraph::build("c1", &lineages);
+
+        let ids: HashSet<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+        for edge in &graph.edges {
+            assert!(ids.contains(edge.from.as_str()), "{} is not a node", edge.from);
+            assert!(ids.contains(edge.to.as_str()), "{} is not a node", edge.to);
+        }
+        assert!(!has_edge(
+            &graph,
+            &fragment_node_id("missing"),
+            &fragment_node_id("f2"),
+            EdgeKind::DerivedInto
+        ));
+    }
+
+    fn result(fragment: Option<&str>) -> ResultMetadata {
+        ResultMetadata {
+            hash: String::new(),
+            size: 0,
+            mime_type: String::new(),
+            uploaded_at: None,
+            step: None,
+            fragment: fragment.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn only_results_are_reported_as_derived() {
+        let lineages = vec![
+            fragment_lineage("f1", "fragmenter", &[], &["input.csv"]),
+            fragment_lineage("f2", "clean", &["f1"], &["cleaned.csv"]),
+            fragment_lineage("f3", "train", &["f2"], &["model.bin"]),
+            fragment_lineage("f4", "report", &["f2"], &["report.pdf"]),
+        ];
+        let (fragments, files) = trace_forward(&lineages, &["f1".to_owned()]);
+        assert_eq!(fragments, vec!["f1", "f2", "f3", "f4"]);
+        assert!(files.contains(&"cleaned.csv".to_owned()));
+
+        let mut results = BTreeMap::new();
+        results.insert("model.bin".to_owned(), result(None));
+        results.insert("report.pdf".to_owned(), result(Some("f4")));
+        results.insert("other.pdf".to_owned(), result(Some("f9")));
+        assert_eq!(
+            derived_results(&results, &fragments, &files),
+            vec!["model.bin", "report.pdf"]
+        );
+    }
+}
@@ -1,3 +1,14 @@
 //! Contains logic with regards to management of pipelines.
+pub mod compare;
+pub mod documents;
+pub mod index;
+pub mod lineage;
+pub mod models;
+pub mod prov;
+pub mod query;
+pub mod reproducibility;
+pub mod retention;
 pub mod routes;
+pub mod stats;
+pub mod validation;
 pub use routes::init_routes;
@@ -0,0 +1,280 @@
+//! Contains models which the daemon uses to describe pipeline executions, in addition to the models from `iterum_rust::pipeline`.
+use crate::error::DaemonError;
+use crate::utils;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Name of the document next to the pipeline execution in which its status is stored.
+pub const STATUS_DOCUMENT: &str = "status";
+
+/// Storage used by a single pipeline execution, split up into its results and its lineage information.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PipelineStorageUsage {
+    pub pipeline_hash: String,
+    pub results_bytes: u64,
+    pub lineage_bytes: u64,
+}
+
+/// The states a pipeline execution can be in. A pipeline execution starts as pending, and ends as either succeeded, failed or cancelled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PipelineStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl PipelineStatus {
+    /// Whether a pipeline execution in this state is allowed to move to the `next` state.
+    pub fn can_transition_to(self, next: PipelineStatus) -> bool {
+        use PipelineStatus::*;
+        match self {
+            Pending => matches!(next, Running | Failed | Cancelled),
+            Running => matches!(next, Succeeded | Failed | Cancelled),
+            Succeeded | Failed | Cancelled => false,
+        }
+    }
+
+    /// Whether the pipeline execution has finished, after which its status can no longer change.
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            PipelineStatus::Succeeded | PipelineStatus::Failed | PipelineStatus::Cancelled
+        )
+    }
+}
+
+impl fmt::Display for PipelineStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self {
+            PipelineStatus::Pending => "pending",
+            PipelineStatus::Running => "running",
+            PipelineStatus::Succeeded => "succeeded",
+            PipelineStatus::Failed => "failed",
+            PipelineStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// A single change of the status of a pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatusTransition {
+    pub status: PipelineStatus,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// The current status of a pipeline execution, together with every transition which led to it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExecutionStatus {
+    pub status: PipelineStatus,
+    pub history: Vec<StatusTransition>,
+}
+
+impl ExecutionStatus {
+    /// Creates the status of a new pipeline execution, which is pending.
+    pub fn new() -> ExecutionStatus {
+        ExecutionStatus {
+            status: PipelineStatus::Pending,
+            history: vec![StatusTransition {
+                status: PipelineStatus::Pending,
+                timestamp: Utc::now(),
+            }],
+        }
+    }
+
+    /// Creates the status of a pipeline execution which was stored without one. It is considered to be pending, but without any history,
+    /// as it is unknown when the pipeline execution was created.
+    pub fn unknown() -> ExecutionStatus {
+        ExecutionStatus {
+            status: PipelineStatus::Pending,
+            history: Vec::new(),
+        }
+    }
+
+    /// Moves the pipeline execution to the `next` state, if this is a valid transition.
+    pub fn transition(&mut self, next: PipelineStatus) -> Result<(), DaemonError> {
+        if !self.status.can_transition_to(next) {
+            return Err(DaemonError::InvalidStatusTransition(self.status, next));
+        }
+        self.status = next;
+        self.history.push(StatusTransition {
+            status: next,
+            timestamp: Utc::now(),
+        });
+        Ok(())
+    }
+
+    /// Time at which the pipeline execution was created.
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.history.first().map(|transition| transition.timestamp)
+    }
+}
+
+impl Default for ExecutionStatus {
+    fn default() -> Self {
+        ExecutionStatus::new()
+    }
+}
+
+/// Request body used to update the status of a pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatusUpdate {
+    pub status: PipelineStatus,
+}
+
+/// An item of a batch of FragmentLineages which could not be stored.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchItemError {
+    /// Position of the item in the batch, starting at zero.
+    pub index: usize,
+    pub message: String,
+}
+
+/// Result of storing a batch of FragmentLineages.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BatchLineageReport {
+    pub stored: usize,
+    pub failed: usize,
+    pub errors: Vec<BatchItemError>,
+}
+
+/// Name of the document next to the pipeline execution in which its promotions are stored.
+pub const PROMOTIONS_DOCUMENT: &str = "promotions";
+
+/// Request body used to promote results of a pipeline execution into a new commit of a dataset.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PromotionRequest {
+    pub dataset: String,
+    pub branch: String,
+    /// Result files to promote. All results of the pipeline execution are promoted when omitted.
+    pub files: Option<Vec<String>>,
+    pub name: Option<String>,
+    pub desc: Option<String>,
+}
+
+/// Results of a pipeline execution which were promoted into a commit of a dataset.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Promotion {
+    pub pipeline_hash: String,
+    pub dataset: String,
+    pub branch: String,
+    pub commit: String,
+    pub files: Vec<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Name of the document next to the pipeline execution in which metadata of its results is stored.
+pub const RESULTS_DOCUMENT: &str = "results";
+
+/// Metadata of a single result file of a pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResultMetadata {
+    /// SHA-256 of the contents of the result file.
+    pub hash: String,
+    pub size: u64,
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default)]
+    pub uploaded_at: Option<DateTime<Utc>>,
+    /// Transformation step which produced the result file, if known.
+    #[serde(default)]
+    pub step: Option<String>,
+    /// Fragment which contained the result file, if known.
+    #[serde(default)]
+    pub fragment: Option<String>,
+}
+
+impl ResultMetadata {
+    /// Determines the metadata of a result file which is present on disk at `path`, and is named `file_name` within the results.
+    pub fn from_file(path: &Path, file_name: &str) -> io::Result<ResultMetadata> {
+        let file_metadata = fs::metadata(path)?;
+        Ok(ResultMetadata {
+            hash: utils::file_digest(path)?,
+            size: file_metadata.len(),
+            mime_type: guess_mime_type(file_name),
+            uploaded_at: file_metadata.modified().ok().map(DateTime::<Utc>::from),
+            step: None,
+            fragment: None,
+        })
+    }
+}
+
+/// Guesses the mime type of a file from its extension, falling back to `application/octet-stream`.
+pub fn guess_mime_type(file_name: &str) -> String {
+    mime_guess::from_path(file_name).first_or_octet_stream().to_string()
+}
+
+/// Query parameters with which the origin of uploaded result files can be specified.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ResultOrigin {
+    pub step: Option<String>,
+    pub fragment: Option<String>,
+}
+
+/// A result file of a pipeline execution together with its metadata, as returned when listing results in detail.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResultListing {
+    pub name: String,
+    #[serde(flatten)]
+    pub metadata: ResultMetadata,
+}
+
+/// Name of the document next to the pipeline execution in which the outcome of its last reproducibility verification is stored.
+pub const REPRODUCIBILITY_DOCUMENT: &str = "reproducibility";
+
+/// Name of the document next to the pipeline execution in which its annotations are stored.
+pub const ANNOTATIONS_DOCUMENT: &str = "annotations";
+
+/// Human metadata attached to a pipeline execution. Pinned pipeline executions are protected from deletion.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Annotations {
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// What was removed when deleting a pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RemovalReport {
+    pub pipeline_hash: String,
+    /// Files of the pipeline execution which were removed, relative to its directory.
+    pub files: Vec<String>,
+    pub bytes: u64,
+    /// Reasons to keep the pipeline execution which were overridden by forcing the deletion.
+    #[serde(default)]
+    pub overridden: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_status_has_no_creation_time() {
+        let mut status = ExecutionStatus::unknown();
+        assert_eq!(status.status, PipelineStatus::Pending);
+        assert_eq!(status.created_at(), None);
+
+        status.transition(PipelineStatus::Running).unwrap();
+        assert_eq!(status.status, PipelineStatus::Running);
+    }
+
+    #[test]
+    fn new_status_is_created_now() {
+        let before = Utc::now();
+        let created_at = ExecutionStatus::new().created_at().unwrap();
+        assert!(created_at >= before && created_at <= Utc::now());
+    }
+}
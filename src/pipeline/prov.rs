@@ -0,0 +1,316 @@
+//! Contains the export of the provenance of a pipeline execution in the W3C PROV data model, serialized as either PROV-JSON or PROV-N.
+//! The pipeline execution is mapped onto a PROV bundle. In this bundle the input commit, the fragments and their files are entities, the transformation steps are activities,
+//! and the pipeline itself is the agent the transformation steps are associated with.
+use iterum_rust::pipeline::PipelineExecution;
+use iterum_rust::provenance::FragmentLineage;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Prefix used for the identifiers of all Iterum elements.
+const PREFIX: &str = "iterum";
+
+/// Namespace the Iterum prefix refers to.
+const NAMESPACE: &str = "urn:iterum:";
+
+/// An element (entity, activity or agent) in a PROV bundle.
+#[derive(Debug, Clone)]
+struct Element {
+    prov_type: Option<String>,
+    label: String,
+}
+
+/// The relations between elements which are used in the mapping.
+#[derive(Debug, Clone)]
+enum Relation {
+    WasGeneratedBy { entity: String, activity: String },
+    Used { activity: String, entity: String },
+    WasDerivedFrom { generated: String, used: String },
+    WasAssociatedWith { activity: String, agent: String },
+    HadMember { collection: String, entity: String },
+}
+
+/// PROV bundle describing a single pipeline execution.
+#[derive(Debug, Clone, Default)]
+pub struct ProvBundle {
+    id: String,
+    entities: BTreeMap<String, Element>,
+    activities: BTreeMap<String, Element>,
+    agents: BTreeMap<String, Element>,
+    relations: Vec<Relation>,
+}
+
+/// Creates a qualified name in the Iterum namespace. Bytes which are not allowed in the local part of a PROV-N qualified name are percent-encoded,
+/// such that different names always result in different qualified names.
+fn qualified_name(kind: &str, name: &str) -> String {
+    let local: String = name
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'.' || byte == b'_' {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect();
+    format!("{}:{}_{}", PREFIX, kind, local)
+}
+
+/// Creates the qualified name of a file. Files are scoped by where they come from, as different files can have the same name:
+/// input files by the commit they were read from, and output files by the fragment which produced them.
+fn file_name(scope: &str, file: &str) -> String {
+    qualified_name("file", &format!("{}/{}", scope, file))
+}
+
+fn element(prov_type: Option<&str>, label: &str) -> Element {
+    Element {
+        prov_type: prov_type.map(str::to_owned),
+        label: label.to_owned(),
+    }
+}
+
+impl ProvBundle {
+    /// Maps a pipeline execution and its FragmentLineages onto a PROV bundle.
+    pub fn build(pipeline_execution: &PipelineExecution, lineages: &[FragmentLineage]) -> ProvBundle {
+        let pipeline_run = &pipeline_execution.pipeline_run;
+        let mut bundle = ProvBundle {
+            id: qualified_name("run", &pipeline_run.pipeline_run_hash),
+            ..ProvBundle::default()
+        };
+
+        let commit = qualified_name("commit", &pipeline_run.input_dataset_commit_hash);
+        bundle.entities.insert(
+            commit.to_string(),
+            element(
+                Some("iterum:DatasetCommit"),
+                &format!(
+                    "{}@{}",
+                    pipeline_run.input_dataset, pipeline_run.input_dataset_commit_hash
+                ),
+            ),
+        );
+        let agent = qualified_name("pipeline", &pipeline_run.name);
+        bundle.agents.insert(
+            agent.to_string(),
+            element(Some("prov:SoftwareAgent"), &pipeline_run.name),
+        );
+
+        for lineage in lineages {
+            let metadata = &lineage.description.metadata;
+            let fragment = qualified_name("fragment", &metadata.fragment_id);
+            let step = qualified_name("step", &lineage.transformation_step);
+
+            bundle.entities.insert(
+                fragment.to_string(),
+                element(Some("prov:Collection"), &metadata.fragment_id),
+            );
+            if !bundle.activities.contains_key(&step) {
+                bundle.activities.insert(
+                    step.to_string(),
+                    element(Some("iterum:TransformationStep"), &lineage.transformation_step),
+                );
+                bundle.relations.push(Relation::WasAssociatedWith {
+                    activity: step.to_string(),
+                    agent: agent.to_string(),
+                });
+            }
+            bundle.relations.push(Relation::WasGeneratedBy {
+                entity: fragment.to_string(),
+                activity: step.to_string(),
+            });
+
+            // Fragments without predecessors are created from the input commit.
+            if metadata.predecessors.is_empty() {
+                bundle.relations.push(Relation::Used {
+                    activity: step.to_string(),
+                    entity: commit.to_string(),
+                });
+                bundle.relations.push(Relation::WasDerivedFrom {
+                    generated: fragment.to_string(),
+                    used: commit.to_string(),
+                });
+            }
+            for predecessor in &metadata.predecessors {
+                let predecessor = qualified_name("fragment", predecessor);
+                bundle.relations.push(Relation::Used {
+                    activity: step.to_string(),
+                    entity: predecessor.to_string(),
+                });
+                bundle.relations.push(Relation::WasDerivedFrom {
+                    generated: fragment.to_string(),
+                    used: predecessor,
+                });
+            }
+
+            let file_scope = if metadata.predecessors.is_empty() {
+                &pipeline_run.input_dataset_commit_hash
+            } else {
+                &metadata.fragment_id
+            };
+            for file in &lineage.description.files {
+                let file_entity = file_name(file_scope, &file.name);
+                bundle
+                    .entities
+                    .insert(file_entity.to_string(), element(Some("iterum:File"), &file.name));
+                bundle.relations.push(Relation::HadMember {
+                    collection: fragment.to_string(),
+                    entity: file_entity,
+                });
+            }
+        }
+
+        bundle
+    }
+
+    /// Serializes the bundle as a PROV-JSON document.
+    pub fn to_prov_json(&self) -> Value {
+        let elements_json = |elements: &BTreeMap<String, Element>| -> Value {
+            let mut map = Map::new();
+            for (id, element) in elements {
+                let mut attributes = Map::new();
+                if let Some(prov_type) = &element.prov_type {
+                    attributes.insert(
+                        "prov:type".to_owned(),
+                        json!({ "$": prov_type, "type": "prov:QUALIFIED_NAME" }),
+                    );
+                }
+                attributes.insert("prov:label".to_owned(), json!(element.label));
+                map.insert(id.to_string(), Value::Object(attributes));
+            }
+            Value::Object(map)
+        };
+
+        let mut relations: BTreeMap<&str, Map<String, Value>> = BTreeMap::new();
+        for (index, relation) in self.relations.iter().enumerate() {
+            let (kind, record) = match relation {
+                Relation::WasGeneratedBy { entity, activity } => (
+                    "wasGeneratedBy",
+                    json!({ "prov:entity": entity, "prov:activity": activity }),
+                ),
+                Relation::Used { activity, entity } => {
+                    ("used", json!({ "prov:activity": activity, "prov:entity": entity }))
+                }
+                Relation::WasDerivedFrom { generated, used } => (
+                    "wasDerivedFrom",
+                    json!({ "prov:generatedEntity": generated, "prov:usedEntity": used }),
+                ),
+                Relation::WasAssociatedWith { activity, agent } => (
+                    "wasAssociatedWith",
+                    json!({ "prov:activity": activity, "prov:agent": agent }),
+                ),
+                Relation::HadMember { collection, entity } => (
+                    "hadMember",
+                    json!({ "prov:collection": collection, "prov:entity": entity }),
+                ),
+            };
+            relations
+                .entry(kind)
+                .or_default()
+                .insert(format!("_:r{}", index), record);
+        }
+
+        let mut bundle = Map::new();
+        bundle.insert("entity".to_owned(), elements_json(&self.entities));
+        bundle.insert("activity".to_owned(), elements_json(&self.activities));
+        bundle.insert("agent".to_owned(), elements_json(&self.agents));
+        for (kind, records) in relations {
+            bundle.insert(kind.to_owned(), Value::Object(records));
+        }
+
+        json!({
+            "prefix": { PREFIX: NAMESPACE },
+            "entity": { &self.id: { "prov:type": { "$": "prov:Bundle", "type": "prov:QUALIFIED_NAME" } } },
+            "bundle": { &self.id: bundle },
+        })
+    }
+
+    /// Serializes the bundle as a PROV-N document.
+    pub fn to_prov_n(&self) -> String {
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        let attributes = |element: &Element| -> String {
+            let mut attributes: Vec<String> = Vec::new();
+            if let Some(prov_type) = &element.prov_type {
+                attributes.push(format!("prov:type='{}'", prov_type));
+            }
+            attributes.push(format!("prov:label=\"{}\"", escape(&element.label)));
+            attributes.join(", ")
+        };
+
+        let mut lines: Vec<String> = vec![
+            "document".to_owned(),
+            format!("  prefix {} <{}>", PREFIX, NAMESPACE),
+            format!("  bundle {}", self.id),
+        ];
+        for (id, element) in &self.entities {
+            lines.push(format!("    entity({}, [{}])", id, attributes(element)));
+        }
+        for (id, element) in &self.activities {
+            lines.push(format!("    activity({}, -, -, [{}])", id, attributes(element)));
+        }
+        for (id, element) in &self.agents {
+            lines.push(format!("    agent({}, [{}])", id, attributes(element)));
+        }
+        for relation in &self.relations {
+            lines.push(match relation {
+                Relation::WasGeneratedBy { entity, activity } => {
+                    format!("    wasGeneratedBy({}, {}, -)", entity, activity)
+                }
+                Relation::Used { activity, entity } => format!("    used({}, {}, -)", activity, entity),
+                Relation::WasDerivedFrom { generated, used } => format!("    wasDerivedFrom({}, {})", generated, used),
+                Relation::WasAssociatedWith { activity, agent } => {
+                    format!("    wasAssociatedWith({}, {}, -)", activity, agent)
+                }
+                Relation::HadMember { collection, entity } => format!("    hadMember({}, {})", collection, entity),
+            });
+        }
+        lines.push("  endBundle".to_owned());
+        lines.push("endDocument".to_owned());
+        lines.join("\n") + "\n"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::lineage::tests::fragment_lineage;
+
+    fn pipeline_execution() -> PipelineExecution {
+        serde_json::from_value(json!({
+            "pipeline_run": {
+                "name": "pipeline",
+                "pipeline_run_hash": "run",
+                "input_dataset": "dataset",
+                "input_dataset_commit_hash": "c1",
+                "steps": [],
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn qualified_names_are_percent_encoded() {
+        assert_eq!(
+            qualified_name("file", "plots/loss 1.png"),
+            "iterum:file_plots%2Floss%201.png"
+        );
+        assert_ne!(qualified_name("file", "a/b"), qualified_name("file", "a_b"));
+        assert_ne!(qualified_name("file", "a/b"), qualified_name("file", "a%2Fb"));
+    }
+
+    #[test]
+    fn files_with_the_same_name_are_separate_entities() {
+        let lineages = vec![
+            fragment_lineage("f1", "fragmenter", &[], &["data.csv"]),
+            fragment_lineage("f2", "clean", &["f1"], &["data.csv"]),
+        ];
+        let bundle = ProvBundle::build(&pipeline_execution(), &lineages);
+
+        assert!(bundle.entities.contains_key(&file_name("c1", "data.csv")));
+        assert!(bundle.entities.contains_key(&file_name("f2", "data.csv")));
+        let files = bundle
+            .entities
+            .values()
+            .filter(|element| element.prov_type.as_deref() == Some("iterum:File"))
+            .count();
+        assert_eq!(files, 2);
+    }
+}
@@ -0,0 +1,242 @@
+//! Contains the querying of pipeline executions known to the daemon. Pipeline executions can be filtered on their dataset, input commit or branch, status, name, labels and creation time, and are returned in pages.
+use crate::config::Config;
+use crate::dataset::history;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::documents::{get_annotations, get_execution_status};
+use crate::pipeline::models::{Annotations, PipelineStatus};
+use chrono::{DateTime, Utc};
+use iterum_rust::pipeline::PipelineExecution;
+use iterum_rust::vc::Dataset;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Filters and pagination options for querying pipeline executions. All filters are optional.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PipelineQuery {
+    pub dataset: Option<String>,
+    pub commit: Option<String>,
+    /// Hash or name of the branch the input commit of the pipeline execution belongs to.
+    pub branch: Option<String>,
+    pub status: Option<PipelineStatus>,
+    pub name: Option<String>,
+    /// Label which the pipeline execution should be annotated with.
+    pub label: Option<String>,
+    pub pinned: Option<bool>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    #[serde(default = "default_page")]
+    pub page: usize,
+    #[serde(default = "default_per_page")]
+    pub per_page: usize,
+    /// Whether the full PipelineExecution should be included for each result.
+    #[serde(default)]
+    pub full: bool,
+}
+
+fn default_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    50
+}
+
+/// Largest number of pipeline executions returned in a single page.
+const MAX_PER_PAGE: usize = 500;
+
+/// Summary of a pipeline execution, as returned when querying pipeline executions.
+#[derive(Serialize)]
+pub struct PipelineListing {
+    pub pipeline_hash: String,
+    pub dataset: String,
+    pub name: String,
+    pub input_commit: String,
+    pub status: PipelineStatus,
+    pub created_at: Option<DateTime<Utc>>,
+    pub annotations: Annotations,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution: Option<PipelineExecution>,
+}
+
+/// A single page of pipeline executions matching a query.
+#[derive(Serialize)]
+pub struct PipelinePage {
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub pipelines: Vec<PipelineListing>,
+}
+
+impl PipelineQuery {
+    /// Whether the input commit of a pipeline execution belongs to the branch in the query.
+    fn matches_branch(&self, datasets: &HashMap<String, Dataset>, dataset_name: &str, commit_hash: &str) -> bool {
+        let branch = match &self.branch {
+            Some(branch) => branch,
+            None => return true,
+        };
+        let vc_dataset = match datasets.get(dataset_name) {
+            Some(vc_dataset) => vc_dataset,
+            None => return false,
+        };
+        match (
+            vc_dataset.commits.get(commit_hash),
+            history::find_branch(vc_dataset, branch),
+        ) {
+            (Some(commit), Ok(branch)) => commit.branch == branch.hash,
+            _ => false,
+        }
+    }
+
+    /// Finds all pipeline executions matching the query, and returns the requested page of them.
+    /// Results are ordered on creation time, most recent first. Documents of a pipeline execution are only read while it can still match the query.
+    pub fn execute(&self, config: &Config) -> Result<PipelinePage, DaemonError> {
+        // Acquire read lock once, to match input commits against the branch in the query.
+        let datasets = config.datasets.read().unwrap();
+        let mut dataset_configs: HashMap<String, Option<DatasetConfig>> = HashMap::new();
+        let mut listings: Vec<PipelineListing> = Vec::new();
+        for (pipeline_hash, dataset_name) in config.pipeline_index.all()? {
+            if self.dataset.as_ref().map_or(false, |dataset| *dataset != dataset_name) {
+                continue;
+            }
+            if !dataset_configs.contains_key(&dataset_name) {
+                let dataset_config = config.local_config.get(&dataset_name)?.map(|value| value.into());
+                dataset_configs.insert(dataset_name.to_string(), dataset_config);
+            }
+            let dataset_config = match &dataset_configs[&dataset_name] {
+                Some(dataset_config) => dataset_config,
+                None => continue,
+            };
+            // Results can be posted before the execution itself, skip those until the execution is stored.
+            let pipeline_execution = match dataset_config
+                .backend
+                .get_pipeline_execution(&dataset_config.name, &pipeline_hash)
+            {
+                Ok(pipeline_execution) => pipeline_execution,
+                Err(DaemonError::NotFound) => continue,
+                Err(error) => return Err(error),
+            };
+            let pipeline_run = &pipeline_execution.pipeline_run;
+            let matches_execution = self
+                .commit
+                .as_ref()
+                .map_or(true, |commit| *commit == pipeline_run.input_dataset_commit_hash)
+                && self.name.as_ref().map_or(true, |name| *name == pipeline_run.name)
+                && self.matches_branch(&datasets, &dataset_name, &pipeline_run.input_dataset_commit_hash);
+            if !matches_execution {
+                continue;
+            }
+
+            let status = get_execution_status(dataset_config, &pipeline_hash)?;
+            let created_at = status.created_at();
+            let matches_status = self.status.map_or(true, |filter| filter == status.status)
+                && self
+                    .since
+                    .map_or(true, |since| created_at.map_or(false, |created_at| created_at >= since))
+                && self
+                    .until
+                    .map_or(true, |until| created_at.map_or(false, |created_at| created_at <= until));
+            if !matches_status {
+                continue;
+            }
+
+            let annotations = get_annotations(dataset_config, &pipeline_hash)?;
+            let matches_annotations = self
+                .label
+                .as_ref()
+                .map_or(true, |label| annotations.labels.contains(label))
+                && self.pinned.map_or(true, |pinned| pinned == annotations.pinned);
+            if !matches_annotations {
+                continue;
+            }
+
+            listings.push(PipelineListing {
+                pipeline_hash,
+                dataset: dataset_name,
+                name: pipeline_run.name.to_string(),
+                input_commit: pipeline_run.input_dataset_commit_hash.to_string(),
+                status: status.status,
+                created_at,
+                annotations,
+                execution: if self.full { Some(pipeline_execution) } else { None },
+            });
+        }
+
+        listings.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then_with(|| a.pipeline_hash.cmp(&b.pipeline_hash))
+        });
+        Ok(paginate(listings, self.page, self.per_page))
+    }
+}
+
+/// Selects a single page of pipeline executions. Pages start at one, and the page size is limited to `MAX_PER_PAGE`.
+fn paginate(listings: Vec<PipelineListing>, page: usize, per_page: usize) -> PipelinePage {
+    let total = listings.len();
+    let page = page.max(1);
+    let per_page = per_page.max(1).min(MAX_PER_PAGE);
+    let pipelines = listings
+        .into_iter()
+        .skip((page - 1).saturating_mul(per_page))
+        .take(per_page)
+        .collect();
+
+    PipelinePage {
+        total,
+        page,
+        per_page,
+        pipelines,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listings(count: usize) -> Vec<PipelineListing> {
+        (0..count)
+            .map(|index| PipelineListing {
+                pipeline_hash: index.to_string(),
+                dataset: "dataset".to_owned(),
+                name: String::new(),
+                input_commit: String::new(),
+                status: PipelineStatus::Pending,
+                created_at: None,
+                annotations: Annotations::default(),
+                execution: None,
+            })
+            .collect()
+    }
+
+    fn hashes(page: &PipelinePage) -> Vec<&str> {
+        page.pipelines
+            .iter()
+            .map(|listing| listing.pipeline_hash.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn pages_start_at_one() {
+        let page = paginate(listings(5), 2, 2);
+        assert_eq!(page.total, 5);
+        assert_eq!(hashes(&page), vec!["2", "3"]);
+        assert_eq!(hashes(&paginate(listings(5), 0, 2)), vec!["0", "1"]);
+        assert_eq!(hashes(&paginate(listings(5), 3, 2)), vec!["4"]);
+    }
+
+    #[test]
+    fn page_size_is_limited() {
+        let page = paginate(listings(MAX_PER_PAGE + 1), 1, usize::MAX);
+        assert_eq!(page.per_page, MAX_PER_PAGE);
+        assert_eq!(page.pipelines.len(), MAX_PER_PAGE);
+        assert_eq!(paginate(listings(1), 1, 0).per_page, 1);
+    }
+
+    #[test]
+    fn pages_beyond_the_end_are_empty() {
+        let page = paginate(listings(5), usize::MAX, MAX_PER_PAGE);
+        assert_eq!(page.total, 5);
+        assert!(page.pipelines.is_empty());
+    }
+}
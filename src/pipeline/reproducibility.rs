@@ -0,0 +1,125 @@
+//! Contains logic to verify whether a pipeline execution is reproducible. Other pipeline executions of the same pipeline definition on the same input commit
+//! are expected to produce byte-identical results, which is checked using the content hashes of their results.
+use crate::config::Config;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::compare;
+use crate::pipeline::documents::{get_execution_status, get_result_metadata};
+use crate::pipeline::models::PipelineStatus;
+use chrono::{DateTime, Utc};
+use iterum_rust::pipeline::PipelineExecution;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Outcome of a reproducibility verification.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    /// All other pipeline executions produced identical results.
+    Reproducible,
+    /// At least one other pipeline execution produced different results.
+    Divergent,
+    /// There are no other pipeline executions to compare with.
+    Unverified,
+}
+
+/// Comparison of the results of the verified pipeline execution with those of another pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunVerification {
+    pub pipeline_hash: String,
+    pub identical: bool,
+    /// Result files which were added, removed or changed in the other pipeline execution.
+    pub differing_results: Vec<String>,
+}
+
+/// Result of verifying the reproducibility of a pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReproducibilityReport {
+    pub pipeline_hash: String,
+    pub dataset: String,
+    pub commit: String,
+    pub verdict: Verdict,
+    pub runs: Vec<RunVerification>,
+    pub verified_at: DateTime<Utc>,
+}
+
+/// The part of a pipeline execution which defines what is computed, which is the configuration of its transformation steps.
+fn pipeline_definition(pipeline_execution: &PipelineExecution) -> Result<Value, DaemonError> {
+    Ok(serde_json::to_value(&pipeline_execution.pipeline_run.steps)?)
+}
+
+/// Verifies the reproducibility of a pipeline execution, by comparing its results with those of every other succeeded pipeline execution
+/// of the same pipeline definition on the same input commit.
+pub fn verify(
+    config: &Config,
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+) -> Result<ReproducibilityReport, DaemonError> {
+    let pipeline_execution = dataset_config
+        .backend
+        .get_pipeline_execution(&dataset_config.name, pipeline_hash)?;
+    let pipeline_run = &pipeline_execution.pipeline_run;
+    let definition = pipeline_definition(&pipeline_execution)?;
+    let results = get_result_metadata(dataset_config, pipeline_hash)?;
+
+    let mut runs = Vec::new();
+    for (other_hash, dataset_name) in config.pipeline_index.all()? {
+        if other_hash == pipeline_hash {
+            continue;
+        }
+        let other_config: DatasetConfig = match config.local_config.get(&dataset_name)? {
+            Some(value) => value.into(),
+            None => continue,
+        };
+        let other_execution = match other_config
+            .backend
+            .get_pipeline_execution(&other_config.name, &other_hash)
+        {
+            Ok(pipeline_execution) => pipeline_execution,
+            Err(DaemonError::NotFound) => continue,
+            Err(error) => return Err(error),
+        };
+        let other_run = &other_execution.pipeline_run;
+        if other_run.input_dataset != pipeline_run.input_dataset
+            || other_run.input_dataset_commit_hash != pipeline_run.input_dataset_commit_hash
+            || pipeline_definition(&other_execution)? != definition
+        {
+            continue;
+        }
+        // Results of pipeline executions which did not succeed are incomplete, so comparing with them says nothing.
+        if get_execution_status(&other_config, &other_hash)?.status != PipelineStatus::Succeeded {
+            continue;
+        }
+
+        let comparison = compare::compare_results(&results, &get_result_metadata(&other_config, &other_hash)?);
+        let differing_results: Vec<String> = comparison
+            .added
+            .into_iter()
+            .chain(comparison.removed)
+            .chain(comparison.changed)
+            .collect();
+        runs.push(RunVerification {
+            pipeline_hash: other_hash,
+            identical: differing_results.is_empty(),
+            differing_results,
+        });
+    }
+    runs.sort_by(|a, b| a.pipeline_hash.cmp(&b.pipeline_hash));
+
+    let verdict = if runs.is_empty() {
+        Verdict::Unverified
+    } else if runs.iter().all(|run| run.identical) {
+        Verdict::Reproducible
+    } else {
+        Verdict::Divergent
+    };
+
+    Ok(ReproducibilityReport {
+        pipeline_hash: pipeline_hash.to_owned(),
+        dataset: pipeline_run.input_dataset.to_string(),
+        commit: pipeline_run.input_dataset_commit_hash.to_string(),
+        verdict,
+        runs,
+        verified_at: Utc::now(),
+    })
+}
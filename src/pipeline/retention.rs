@@ -0,0 +1,431 @@
+//! Contains the application of retention policies to the pipeline executions of a dataset, and the log of what was deleted by them.
+//! Retention policies are applied periodically by a background task, and can be applied on demand as well.
+use crate::config::Config;
+use crate::dataset::models::RetentionPolicy;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::documents::protection_reasons;
+use crate::pipeline::models::{ExecutionStatus, STATUS_DOCUMENT};
+use actix_web::web;
+use chrono::{DateTime, Duration, Utc};
+use iterum_rust::vc::Dataset;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+
+/// Name of the tree in the local kv-store which contains the retention log.
+const RETENTION_LOG_TREE: &str = "retention_log";
+
+/// Separator between the dataset name and the timestamp in keys of the retention log.
+const KEY_SEPARATOR: char = '\0';
+
+/// Interval in seconds at which retention policies are applied, when `RETENTION_INTERVAL_SECS` is not set.
+const DEFAULT_RETENTION_INTERVAL_SECS: u64 = 3600;
+
+/// Largest age in days a retention policy can keep pipeline executions for. Larger values are limited to it, so the cutoff time cannot overflow.
+const MAX_KEEP_DAYS: i64 = 1_000_000;
+
+/// Outcome of applying the retention policy of a dataset once.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RetentionRecord {
+    pub dataset: String,
+    pub timestamp: DateTime<Utc>,
+    pub dry_run: bool,
+    /// Pipeline executions which were deleted, or would have been deleted in case of a dry run.
+    pub deleted: Vec<String>,
+    /// Pipeline executions which should have been deleted, but could not be.
+    #[serde(default)]
+    pub failed: Vec<RetentionFailure>,
+}
+
+/// A pipeline execution which could not be deleted by a retention policy, and the reason why.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RetentionFailure {
+    pub pipeline_hash: String,
+    pub error: String,
+}
+
+/// Log of the pipeline executions deleted by retention policies. Records are stored per dataset, ordered on time.
+pub struct RetentionLog {
+    tree: sled::Tree,
+}
+
+impl RetentionLog {
+    /// Opens the log in the local kv-store, creating it if it does not exist yet.
+    pub fn open(db: &sled::Db) -> Result<RetentionLog, DaemonError> {
+        Ok(RetentionLog {
+            tree: db.open_tree(RETENTION_LOG_TREE)?,
+        })
+    }
+
+    fn prefix(dataset_name: &str) -> String {
+        format!("{}{}", dataset_name, KEY_SEPARATOR)
+    }
+
+    /// Adds a record to the log.
+    pub fn insert(&self, record: &RetentionRecord) -> Result<(), DaemonError> {
+        let key = format!("{}{}", Self::prefix(&record.dataset), record.timestamp.to_rfc3339());
+        self.tree.insert(key, serde_json::to_vec(record)?)?;
+        Ok(())
+    }
+
+    /// Retrieves the records of a dataset, oldest first.
+    pub fn for_dataset(&self, dataset_name: &str) -> Result<Vec<RetentionRecord>, DaemonError> {
+        self.tree
+            .scan_prefix(Self::prefix(dataset_name))
+            .values()
+            .map(|value| -> Result<RetentionRecord, DaemonError> { Ok(serde_json::from_slice(&value?)?) })
+            .collect()
+    }
+
+    /// Removes all records of a dataset from the log.
+    pub fn remove_dataset(&self, dataset_name: &str) -> Result<(), DaemonError> {
+        for key in self.tree.scan_prefix(Self::prefix(dataset_name)).keys() {
+            self.tree.remove(key?)?;
+        }
+        Ok(())
+    }
+}
+
+/// A pipeline execution of a dataset, as considered by a retention policy.
+struct RetentionCandidate {
+    pipeline_hash: String,
+    created_at: Option<DateTime<Utc>>,
+    protected: bool,
+}
+
+/// Whether a pipeline execution is kept regardless of the retention policy, for the same reasons that deleting it is refused.
+pub fn is_protected(
+    config: &Config,
+    datasets: &HashMap<String, Dataset>,
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+) -> Result<bool, DaemonError> {
+    Ok(!protection_reasons(config, datasets, dataset_config, pipeline_hash)?.is_empty())
+}
+
+/// Pipeline executions which have not finished yet, or whose state is unknown, are protected as well.
+fn retention_candidate(
+    config: &Config,
+    datasets: &HashMap<String, Dataset>,
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+) -> Result<RetentionCandidate, DaemonError> {
+    // Pipeline executions stored before statuses were tracked have an unknown state and creation time, so they are never deleted automatically.
+    let (finished, created_at) = match dataset_config.backend.get_pipeline_document::<ExecutionStatus>(
+        &dataset_config.name,
+        pipeline_hash,
+        STATUS_DOCUMENT,
+    ) {
+        Ok(status) => (status.status.is_finished(), status.created_at()),
+        Err(DaemonError::NotFound) => (false, None),
+        Err(error) => return Err(error),
+    };
+    Ok(RetentionCandidate {
+        pipeline_hash: pipeline_hash.to_owned(),
+        created_at,
+        protected: !finished || created_at.is_none() || is_protected(config, datasets, dataset_config, pipeline_hash)?,
+    })
+}
+
+/// Checks that a retention policy can be set or applied: it cannot have a negative age, or keep no pipeline executions at all.
+pub fn validate_policy(policy: &RetentionPolicy) -> Result<(), DaemonError> {
+    if policy.keep_days.map_or(false, |keep_days| keep_days < 0) {
+        return Err(DaemonError::BadRequest(
+            "The number of days to keep pipeline executions cannot be negative.".to_owned(),
+        ));
+    }
+    if policy.keeps_nothing() {
+        return Err(DaemonError::BadRequest(
+            "A policy which keeps no pipeline executions at all is not allowed.".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Selects the candidates which are not kept by a retention policy, at time `now`.
+fn expired_candidates(
+    mut candidates: Vec<RetentionCandidate>,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    if policy.keeps_everything() {
+        return Vec::new();
+    }
+
+    // Most recent first. Pipeline executions with an unknown creation time are considered to be the oldest.
+    candidates.sort_by(|a, b| {
+        b.created_at
+            .cmp(&a.created_at)
+            .then_with(|| a.pipeline_hash.cmp(&b.pipeline_hash))
+    });
+
+    let cutoff = policy
+        .keep_days
+        .map(|days| now - Duration::days(days.max(0).min(MAX_KEEP_DAYS)));
+    candidates
+        .into_iter()
+        .enumerate()
+        .filter(|(position, candidate)| {
+            let kept_by_count = policy.keep_last.map_or(false, |keep_last| *position < keep_last);
+            let kept_by_age = cutoff.map_or(false, |cutoff| {
+                candidate.created_at.map_or(false, |created_at| created_at >= cutoff)
+            });
+            !(candidate.protected || kept_by_count || kept_by_age)
+        })
+        .map(|(_, candidate)| candidate.pipeline_hash)
+        .collect()
+}
+
+/// Selects the pipeline executions of a dataset which are not kept by its retention policy.
+pub fn select_expired(
+    config: &Config,
+    dataset_config: &DatasetConfig,
+    policy: &RetentionPolicy,
+) -> Result<Vec<String>, DaemonError> {
+    if policy.keeps_everything() {
+        return Ok(Vec::new());
+    }
+
+    // Acquire read lock, to check whether promoted results are consumed by other pipeline executions.
+    let datasets_ref = config.datasets.read().unwrap();
+    let mut candidates = Vec::new();
+    for (pipeline_hash, dataset_name) in config.pipeline_index.all()? {
+        if dataset_name == dataset_config.name {
+            candidates.push(retention_candidate(
+                config,
+                &datasets_ref,
+                dataset_config,
+                &pipeline_hash,
+            )?);
+        }
+    }
+    Ok(expired_candidates(candidates, policy, Utc::now()))
+}
+
+/// Applies the retention policy of a dataset, deleting the pipeline executions which are not kept. Unless it is a dry run, the outcome is recorded in the retention log.
+pub fn apply(config: &Config, dataset_config: &DatasetConfig, dry_run: bool) -> Result<RetentionRecord, DaemonError> {
+    let policy = dataset_config.retention.clone().unwrap_or_default();
+    // Policies which were stored before they were validated are refused here as well.
+    validate_policy(&policy)?;
+    let expired = select_expired(config, dataset_config, &policy)?;
+    let mut record = RetentionRecord {
+        dataset: dataset_config.name.to_string(),
+        timestamp: Utc::now(),
+        dry_run,
+        deleted: Vec::new(),
+        failed: Vec::new(),
+    };
+    if dry_run {
+        record.deleted = expired;
+        return Ok(record);
+    }
+
+    // Failures are recorded per pipeline execution, such that the others are still deleted and the deletions are still logged.
+    for pipeline_hash in expired {
+        match delete_expired(config, dataset_config, &pipeline_hash) {
+            Ok(true) => record.deleted.push(pipeline_hash),
+            Ok(false) => {}
+            Err(error) => {
+                error!(
+                    "Retention policy of dataset {} could not delete pipeline execution {}: {}",
+                    dataset_config.name, pipeline_hash, error
+                );
+                record.failed.push(RetentionFailure {
+                    pipeline_hash,
+                    error: error.to_string(),
+                });
+            }
+        }
+    }
+    if !record.deleted.is_empty() || !record.failed.is_empty() {
+        config.retention_log.insert(&record)?;
+    }
+    Ok(record)
+}
+
+/// Deletes an expired pipeline execution, unless it was protected in the meantime. Returns whether it was deleted.
+/// When its files cannot be removed, the pipeline execution is indexed again, such that it is not left behind unindexed.
+fn delete_expired(config: &Config, dataset_config: &DatasetConfig, pipeline_hash: &str) -> Result<bool, DaemonError> {
+    // Acquire write lock while checking the pipeline execution again and removing it from the indexes, so it cannot be pinned or promoted
+    // while it is being deleted. Once it is no longer indexed, its files are removed without holding the lock.
+    {
+        let datasets_ref = config.datasets.write().unwrap();
+        if is_protected(config, &datasets_ref, dataset_config, pipeline_hash)? {
+            return Ok(false);
+        }
+        config.pipeline_index.remove(pipeline_hash)?;
+        config.lineage_index.remove_pipeline(pipeline_hash)?;
+    }
+
+    info!(
+        "Retention policy of dataset {} deletes pipeline execution {}",
+        dataset_config.name, pipeline_hash
+    );
+    match dataset_config
+        .backend
+        .remove_pipeline_execution(dataset_config, pipeline_hash, &config.pipeline_index)
+    {
+        // The pipeline execution was already removed from the storage backend.
+        Ok(_) | Err(DaemonError::NotFound) => Ok(true),
+        Err(error) => {
+            let datasets_ref = config.datasets.read().unwrap();
+            let restored = config
+                .pipeline_index
+                .insert(pipeline_hash, &dataset_config.name)
+                .and_then(|()| match datasets_ref.get(&dataset_config.name) {
+                    Some(vc_dataset) => config
+                        .lineage_index
+                        .index_pipeline(dataset_config, vc_dataset, pipeline_hash),
+                    None => Ok(()),
+                });
+            if let Err(restore_error) = restored {
+                warn!(
+                    "Could not index pipeline execution {} again after failing to delete it: {}",
+                    pipeline_hash, restore_error
+                );
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Applies the retention policies of all datasets which have one. Failures are logged per dataset, such that they do not prevent the policies of other datasets from being applied.
+pub fn apply_all(config: &Config) -> Result<(), DaemonError> {
+    for kv in config.local_config.iter() {
+        let (_key, value) = kv?;
+        let dataset_config: DatasetConfig = value.into();
+        if dataset_config.retention.is_some() {
+            if let Err(error) = apply(config, &dataset_config, false) {
+                error!(
+                    "Applying retention policy of dataset {} failed: {}",
+                    dataset_config.name, error
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Spawns a background task which periodically applies the retention policies of all datasets. The interval is read from `RETENTION_INTERVAL_SECS`,
+/// where an interval of zero disables the task.
+pub fn spawn_retention_task(config: web::Data<Config>) {
+    let interval_secs = env::var("RETENTION_INTERVAL_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_INTERVAL_SECS);
+    if interval_secs == 0 {
+        info!("Retention policies are only applied on request.");
+        return;
+    }
+
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(std::time::Duration::from_secs(interval_secs));
+        // The first tick completes immediately, the policies are first applied one interval after startup.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            debug!("Applying retention policies.");
+            // Applying the policies reads and removes files, so it runs on the blocking thread pool instead of the arbiter.
+            let config = config.clone();
+            if let Err(error) = web::block(move || apply_all(&config)).await {
+                error!("Applying retention policies failed: {}", error);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(
+        pipeline_hash: &str,
+        age_days: Option<i64>,
+        protected: bool,
+        now: DateTime<Utc>,
+    ) -> RetentionCandidate {
+        RetentionCandidate {
+            pipeline_hash: pipeline_hash.to_owned(),
+            created_at: age_days.map(|days| now - Duration::days(days)),
+            protected,
+        }
+    }
+
+    fn policy(keep_last: Option<usize>, keep_days: Option<i64>) -> RetentionPolicy {
+        RetentionPolicy { keep_last, keep_days }
+    }
+
+    #[test]
+    fn keeps_the_most_recent_executions() {
+        let now = Utc::now();
+        let candidates = vec![
+            candidate("old", Some(30), false, now),
+            candidate("new", Some(1), false, now),
+            candidate("middle", Some(10), false, now),
+        ];
+        assert_eq!(expired_candidates(candidates, &policy(Some(2), None), now), vec!["old"]);
+    }
+
+    #[test]
+    fn keeps_executions_younger_than_the_cutoff() {
+        let now = Utc::now();
+        let candidates = vec![
+            candidate("old", Some(30), false, now),
+            candidate("new", Some(1), false, now),
+        ];
+        assert_eq!(expired_candidates(candidates, &policy(None, Some(7)), now), vec!["old"]);
+    }
+
+    #[test]
+    fn either_rule_keeps_an_execution() {
+        let now = Utc::now();
+        let candidates = vec![
+            candidate("a", Some(30), false, now),
+            candidate("b", Some(20), false, now),
+            candidate("c", Some(1), false, now),
+        ];
+        assert_eq!(
+            expired_candidates(candidates, &policy(Some(2), Some(7)), now),
+            vec!["a"]
+        );
+    }
+
+    #[test]
+    fn protected_executions_are_kept() {
+        let now = Utc::now();
+        let candidates = vec![
+            candidate("pinned", Some(30), true, now),
+            candidate("old", Some(20), false, now),
+        ];
+        assert_eq!(
+            expired_candidates(candidates, &policy(Some(0), Some(7)), now),
+            vec!["old"]
+        );
+    }
+
+    #[test]
+    fn policies_without_rules_keep_everything() {
+        let now = Utc::now();
+        let candidates = vec![candidate("old", Some(30), false, now)];
+        assert!(expired_candidates(candidates, &policy(None, None), now).is_empty());
+    }
+
+    #[test]
+    fn large_ages_do_not_overflow() {
+        let now = Utc::now();
+        let candidates = vec![candidate("old", Some(30), false, now)];
+        assert!(expired_candidates(candidates, &policy(None, Some(i64::MAX)), now).is_empty());
+    }
+
+    #[test]
+    fn policies_which_keep_nothing_are_rejected() {
+        assert!(validate_policy(&policy(Some(0), None)).is_err());
+        assert!(validate_policy(&policy(None, Some(0))).is_err());
+        assert!(validate_policy(&policy(Some(0), Some(0))).is_err());
+        assert!(validate_policy(&policy(None, Some(-1))).is_err());
+        assert!(validate_policy(&policy(Some(0), Some(1))).is_ok());
+        assert!(validate_policy(&policy(Some(1), Some(0))).is_ok());
+        assert!(validate_policy(&policy(None, None)).is_ok());
+    }
+}
@@ -0,0 +1,59 @@
+//! Contains routes with regards to the annotations of a pipeline execution, which are stored next to the pipeline execution without changing it.
+use super::helpers::find_dataset_conf_for_pipeline_hash;
+use crate::config;
+use crate::error::DaemonError;
+use crate::pipeline::documents::get_annotations;
+use crate::pipeline::models::{Annotations, ANNOTATIONS_DOCUMENT};
+use actix_web::{get, put, web, HttpResponse};
+
+/// Retrieve the annotations of a pipeline execution
+#[get("/pipelines/{pipeline_hash}/annotations")]
+async fn get_pipeline_annotations(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!("Getting annotations of pipeline execution {}", pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let annotations = get_annotations(&dataset_config, &pipeline_hash)?;
+
+    Ok(HttpResponse::Ok().json(&annotations))
+}
+
+/// Replace the annotations of a pipeline execution, such as its display name, notes, labels and whether it is pinned
+#[put("/pipelines/{pipeline_hash}/annotations")]
+async fn set_pipeline_annotations(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    annotations: web::Json<Annotations>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    let mut annotations = annotations.into_inner();
+    info!("Setting annotations of pipeline execution {}", pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    annotations.labels.retain(|label| !label.trim().is_empty());
+    annotations.labels.sort();
+    annotations.labels.dedup();
+
+    // Acquire write lock, so a pipeline execution cannot be unpinned while it is being deleted.
+    {
+        let _datasets_ref = config.datasets.write().unwrap();
+        dataset_config.backend.store_pipeline_document(
+            &dataset_config.name,
+            &pipeline_hash,
+            ANNOTATIONS_DOCUMENT,
+            &annotations,
+        )?;
+    }
+
+    Ok(HttpResponse::Ok().json(&annotations))
+}
@@ -0,0 +1,52 @@
+//! Contains routes to compare pipeline executions with each other
+use super::helpers::find_dataset_conf_for_pipeline_hash;
+use crate::config;
+use crate::error::DaemonError;
+use crate::pipeline::compare::{self, RunComparison};
+use crate::pipeline::documents::get_result_metadata;
+use crate::pipeline::lineage;
+use actix_web::{get, web, HttpResponse};
+
+/// Compares two pipeline executions. Reports differences in their input commit, the configuration of their transformation steps,
+/// the number of fragments handled per step, and which result files were added, removed or changed.
+#[get("/pipelines/compare/{pipeline_hash_a}/{pipeline_hash_b}")]
+async fn compare_pipeline_executions(
+    config: web::Data<config::Config>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, DaemonError> {
+    let (pipeline_hash_a, pipeline_hash_b) = path.into_inner();
+    info!(
+        "Comparing pipeline executions {} and {}",
+        pipeline_hash_a, pipeline_hash_b
+    );
+
+    let dataset_config_a = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash_a)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let dataset_config_b = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash_b)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let execution_a = dataset_config_a
+        .backend
+        .get_pipeline_execution(&dataset_config_a.name, &pipeline_hash_a)?;
+    let execution_b = dataset_config_b
+        .backend
+        .get_pipeline_execution(&dataset_config_b.name, &pipeline_hash_b)?;
+    let lineages_a = lineage::load_lineages(&dataset_config_a, &pipeline_hash_a)?;
+    let lineages_b = lineage::load_lineages(&dataset_config_b, &pipeline_hash_b)?;
+    let results_a = get_result_metadata(&dataset_config_a, &pipeline_hash_a)?;
+    let results_b = get_result_metadata(&dataset_config_b, &pipeline_hash_b)?;
+
+    let comparison = RunComparison {
+        input: compare::compare_inputs(&execution_a, &execution_b),
+        steps: compare::compare_steps(&execution_a, &execution_b, &lineages_a, &lineages_b)?,
+        results: compare::compare_results(&results_a, &results_b),
+        a: pipeline_hash_a,
+        b: pipeline_hash_b,
+    };
+
+    Ok(HttpResponse::Ok().json(&comparison))
+}
@@ -1,10 +1,15 @@
 //! Contains the routes related to a PipelineExecution, which is a specific instance of a PipelineRun, with corresponding lineage info, status and results.
-use super::helpers::{find_all_pipelines, find_dataset_conf_for_pipeline_hash};
+use super::helpers::find_dataset_conf_for_pipeline_hash;
 use crate::config;
 use crate::dataset::models::DatasetConfig;
 use crate::error::DaemonError;
-use actix_web::{delete, get, post, web, HttpResponse};
+use crate::openlineage::EventType;
+use crate::pipeline::documents::{get_promotions, protection_reasons};
+use crate::pipeline::models::{ExecutionStatus, STATUS_DOCUMENT};
+use crate::pipeline::query::PipelineQuery;
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
 use iterum_rust::pipeline::PipelineExecution;
+use serde::Deserialize;
 
 /// Retrieve the different pipeline executions for a dataset
 #[get("/{dataset}/pipelines")]
@@ -26,12 +31,29 @@ async fn get_dataset_pipeline_executions(
     Ok(HttpResponse::Ok().json(&pipeline_executions))
 }
 
-/// Retrieve the different pipeline executions for all datasets
+/// Retrieve the different pipeline executions for all datasets. Without query parameters, the hashes of all pipeline executions are returned.
+/// Otherwise the pipeline executions can be filtered on dataset, input commit or branch, status, name and creation time,
+/// and are returned in pages. Optionally the full PipelineExecution is included for each of them.
 #[get("/pipelines")]
-async fn get_pipeline_executions(config: web::Data<config::Config>) -> Result<HttpResponse, DaemonError> {
-    info!("Getting pipeline executions");
-    let pipeline_executions = find_all_pipelines(&config.local_config);
-    Ok(HttpResponse::Ok().json(&pipeline_executions))
+async fn get_pipeline_executions(
+    config: web::Data<config::Config>,
+    request: HttpRequest,
+    query: web::Query<PipelineQuery>,
+) -> Result<HttpResponse, DaemonError> {
+    if request.query_string().is_empty() {
+        info!("Getting pipeline executions");
+        let pipeline_hashes: Vec<String> = config
+            .pipeline_index
+            .all()?
+            .into_iter()
+            .map(|(pipeline_hash, _)| pipeline_hash)
+            .collect();
+        return Ok(HttpResponse::Ok().json(&pipeline_hashes));
+    }
+
+    info!("Getting pipeline executions matching {:?}", query);
+    let pipeline_page = query.execute(&config)?;
+    Ok(HttpResponse::Ok().json(&pipeline_page))
 }
 
 /// Retrieve a specific pipeline execution, without knowing the dataset beforehand
@@ -43,7 +65,7 @@ async fn get_pipeline_execution_without_dataset(
     let pipeline_hash = path.into_inner();
     info!("Getting pipeline execution with pipeline hash {}", pipeline_hash);
 
-    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config.local_config, &pipeline_hash) {
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
         Some(conf) => conf,
         None => return Ok(HttpResponse::NotFound().finish()),
     };
@@ -98,31 +120,80 @@ async fn create_pipeline_execution(
         .ok_or_else(|| DaemonError::NotFound)?
         .into();
 
+    let pipeline_hash = &pipeline_execution.pipeline_run.pipeline_run_hash;
+    let is_new = match dataset_config
+        .backend
+        .get_pipeline_execution(&dataset_config.name, pipeline_hash)
+    {
+        Ok(_) => false,
+        Err(DaemonError::NotFound) => true,
+        Err(error) => return Err(error),
+    };
+
     dataset_config
         .backend
-        .store_pipeline_execution(&dataset_config, &pipeline_execution)?;
+        .store_pipeline_execution(&dataset_config, &pipeline_execution, &config.pipeline_index)?;
+    // Only new pipeline executions start out as pending, storing an existing execution again keeps its status.
+    if is_new {
+        dataset_config.backend.store_pipeline_document(
+            &dataset_config.name,
+            pipeline_hash,
+            STATUS_DOCUMENT,
+            &ExecutionStatus::new(),
+        )?;
+        if let Some(openlineage) = &config.openlineage {
+            openlineage.emit_for(EventType::Start, &pipeline_execution);
+        }
+    }
 
     Ok(HttpResponse::Ok().json(&pipeline_execution))
 }
 
-/// Delete a specific pipeline execution
+/// Query parameters for deleting a pipeline execution.
+#[derive(Deserialize, Debug)]
+pub struct DeleteOptions {
+    #[serde(default)]
+    force: bool,
+}
+
+/// Delete a specific pipeline execution, and report which files were removed.
+/// Pinned pipeline executions and pipeline executions whose results were promoted into a dataset are only deleted with `?force=true`.
 #[delete("/pipelines/{pipeline_hash}")]
 async fn delete_pipeline_execution(
     config: web::Data<config::Config>,
     path: web::Path<String>,
+    options: web::Query<DeleteOptions>,
 ) -> Result<HttpResponse, DaemonError> {
-    info!("Deleting new pipeline execution");
-
     let pipeline_hash = path.into_inner();
+    info!("Deleting pipeline execution {}", pipeline_hash);
 
-    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config.local_config, &pipeline_hash) {
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
         Some(conf) => conf,
         None => return Ok(HttpResponse::NotFound().finish()),
     };
 
-    dataset_config
-        .backend
-        .remove_pipeline_execution(&dataset_config, &pipeline_hash)?;
+    // Acquire write lock, so the pipeline execution cannot be pinned or promoted while it is being deleted.
+    let report = {
+        let datasets_ref = config.datasets.write().unwrap();
+        let blockers = protection_reasons(&config, &datasets_ref, &dataset_config, &pipeline_hash)?;
+        if !blockers.is_empty() && !options.force {
+            return Err(DaemonError::Protected(format!(
+                "{} Use force to delete it anyway.",
+                blockers.join(" ")
+            )));
+        }
+
+        let promotions = get_promotions(&dataset_config, &pipeline_hash)?;
+        let mut report = dataset_config.backend.remove_pipeline_execution(
+            &dataset_config,
+            &pipeline_hash,
+            &config.pipeline_index,
+        )?;
+        report.overridden = blockers;
+        config.lineage_index.remove_pipeline(&pipeline_hash)?;
+        config.promotion_index.remove_promotions(&promotions)?;
+        report
+    };
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(HttpResponse::Ok().json(&report))
 }
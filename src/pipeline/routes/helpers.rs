@@ -1,35 +1,16 @@
 //! Contains some helper functions, which are used by some of the route endpoints
+use crate::config::Config;
 use crate::dataset::models::DatasetConfig;
+use crate::error::DaemonError;
 
-/// Helper function to find a DatasetConfig, by iterating over the datasets known to the daemon
-pub fn find_dataset_conf_for_pipeline_hash(db: &sled::Db, pipeline_hash: &str) -> Option<DatasetConfig> {
-    db.iter()
-        .map(|elem| elem.unwrap())
-        .map(|(_key, value)| {
-            // let dataset_name: String = key.into();
-            let dataset_conf: DatasetConfig = value.into();
-            dataset_conf
-        })
-        .find(|conf| {
-            conf.backend
-                .get_pipeline_executions(&conf.name)
-                .unwrap()
-                .contains(&pipeline_hash.to_owned())
-        })
-}
-
-/// Helper function to find all pipelines known to the daemon
-pub fn find_all_pipelines(db: &sled::Db) -> Vec<String> {
-    db.iter()
-        .map(|elem| elem.unwrap())
-        .map(|(_key, value)| {
-            // let dataset_name: String = key.into();
-            let dataset_conf: DatasetConfig = value.into();
-            dataset_conf
-        })
-        .fold(Vec::new(), |mut acc, conf| {
-            let mut pipeline_hashes = conf.backend.get_pipeline_executions(&conf.name).unwrap();
-            acc.append(&mut pipeline_hashes);
-            acc
-        })
+/// Helper function to find the DatasetConfig of the dataset in which a pipeline execution is stored, using the pipeline index
+pub fn find_dataset_conf_for_pipeline_hash(
+    config: &Config,
+    pipeline_hash: &str,
+) -> Result<Option<DatasetConfig>, DaemonError> {
+    let dataset_name = match config.pipeline_index.get(pipeline_hash)? {
+        Some(dataset_name) => dataset_name,
+        None => return Ok(None),
+    };
+    Ok(config.local_config.get(&dataset_name)?.map(|value| value.into()))
 }
@@ -1,13 +1,21 @@
 //! Contains routes with regards to management of pipelines. The routes are further split up into submodules.
 
+pub mod annotations;
+pub mod compare;
 pub mod execution;
 mod helpers;
+pub mod promotion;
 pub mod provenance;
+pub mod reproducibility;
 pub mod results;
+pub mod stats;
+pub mod status;
 use actix_web::web;
 
 /// Initializes the different routes, such that Actix exposes the endpoints
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(compare::compare_pipeline_executions);
+    cfg.service(results::get_pipeline_results_archive);
     cfg.service(results::get_pipeline_result);
     cfg.service(results::get_pipeline_results);
     cfg.service(results::add_result);
@@ -17,7 +25,20 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(execution::get_pipeline_execution_without_dataset);
     cfg.service(execution::create_pipeline_execution);
     cfg.service(execution::delete_pipeline_execution);
+    cfg.service(annotations::get_pipeline_annotations);
+    cfg.service(annotations::set_pipeline_annotations);
+    cfg.service(stats::get_pipeline_stats);
+    cfg.service(status::get_pipeline_status);
+    cfg.service(status::update_pipeline_status);
     cfg.service(provenance::post_fragment_lineage);
+    cfg.service(provenance::post_fragment_lineage_batch);
     cfg.service(provenance::get_fragment_lineages);
+    cfg.service(provenance::get_lineage_graph);
+    cfg.service(provenance::trace_result);
+    cfg.service(provenance::get_file_impact);
     cfg.service(provenance::get_fragment_lineage);
+    cfg.service(promotion::promote_results);
+    cfg.service(promotion::get_pipeline_promotions);
+    cfg.service(reproducibility::verify_reproducibility);
+    cfg.service(reproducibility::get_reproducibility);
 }
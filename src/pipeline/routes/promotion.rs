@@ -0,0 +1,179 @@
+//! Contains routes with regards to promoting results of a pipeline execution into a dataset, such that they become versioned inputs of later pipeline executions.
+use super::helpers::find_dataset_conf_for_pipeline_hash;
+use crate::config;
+use crate::dataset::history;
+use crate::dataset::DatasetConfig;
+use crate::error::DaemonError;
+use crate::pipeline::documents::get_promotions;
+use crate::pipeline::models::{Promotion, PromotionRequest, PROMOTIONS_DOCUMENT};
+use crate::utils as daemon_utils;
+use actix_web::{get, post, web, HttpResponse};
+use chrono::Utc;
+use iterum_rust::utils;
+use std::fs;
+use std::path::Path;
+
+/// Creates a new commit on a branch of a dataset, containing results of a pipeline execution.
+/// The promotion is recorded next to the pipeline execution, such that the provenance of the promoted files is retained.
+#[post("/pipelines/{pipeline_hash}/promote")]
+async fn promote_results(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    request: web::Json<PromotionRequest>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    let request = request.into_inner();
+    info!(
+        "Promoting results of pipeline execution {} to branch {} of dataset {}",
+        pipeline_hash, request.branch, request.dataset
+    );
+
+    let pipeline_dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let target_config: DatasetConfig = config
+        .local_config
+        .get(&request.dataset)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    let results = match pipeline_dataset_config.get_pipeline_results(&pipeline_hash) {
+        Ok(results) => results,
+        Err(DaemonError::NotFound) => Vec::new(),
+        Err(error) => return Err(error),
+    };
+    let files = match request.files.clone() {
+        Some(files) => {
+            if let Some(missing) = files.iter().find(|file| !results.contains(file)) {
+                return Err(DaemonError::BadRequest(format!(
+                    "Pipeline execution {} has no result {}",
+                    pipeline_hash, missing
+                )));
+            }
+            files
+        }
+        None => results,
+    };
+    if files.is_empty() {
+        return Err(DaemonError::BadRequest(format!(
+            "Pipeline execution {} has no results to promote",
+            pipeline_hash
+        )));
+    }
+
+    // The temporary folder is removed regardless of the outcome.
+    let temp_path = format!("./.tmp/{}/", utils::create_random_hash());
+    let result = promote_files(
+        &config,
+        &pipeline_dataset_config,
+        &target_config,
+        &pipeline_hash,
+        request,
+        files,
+        Path::new(&temp_path),
+    );
+    if let Err(error) = fs::remove_dir_all(&temp_path) {
+        warn!("Could not remove temporary directory {}: {}", temp_path, error);
+    }
+    let promotion = result?;
+
+    Ok(HttpResponse::Ok().json(&promotion))
+}
+
+/// Commits results of a pipeline execution to the target dataset, using the temporary folder to stage them, and records the promotion.
+fn promote_files(
+    config: &config::Config,
+    pipeline_dataset_config: &DatasetConfig,
+    target_config: &DatasetConfig,
+    pipeline_hash: &str,
+    request: PromotionRequest,
+    files: Vec<String>,
+    temp_path: &Path,
+) -> Result<Promotion, DaemonError> {
+    // Retrieve the results before acquiring the lock, as the hash of the commit is not known yet.
+    let results_path = temp_path.join("results");
+    for file in &files {
+        pipeline_dataset_config.copy_pipeline_result(pipeline_hash, file, &results_path.join(file))?;
+    }
+
+    let name = request
+        .name
+        .unwrap_or_else(|| format!("Results of pipeline {}", pipeline_hash));
+    let desc = request
+        .desc
+        .unwrap_or_else(|| format!("Promoted from pipeline execution {}", pipeline_hash));
+
+    // Acquire write lock, such that the head of the branch cannot move while committing.
+    let mut datasets_ref = config.datasets.write().unwrap();
+    let vc_dataset = datasets_ref
+        .get(&target_config.name)
+        .ok_or_else(|| DaemonError::NotFound)?
+        .clone();
+    let (vc_dataset, commit) = history::commit_files(vc_dataset, &request.branch, &name, &desc, &files)?;
+
+    // Stage the results under the names with which they should be stored in the backend.
+    let staging_path = temp_path.join("staged");
+    for file in &files {
+        let staged_file = staging_path.join(history::stored_file_name(file, &commit.hash));
+        daemon_utils::link_or_copy(&results_path.join(file), &staged_file)?;
+    }
+    target_config.store_committed_files(&commit, staging_path.to_string_lossy().into_owned())?;
+
+    // The promotion is recorded before the commit is published, such that a published commit is never missing its promotion.
+    let promotion = Promotion {
+        pipeline_hash: pipeline_hash.to_string(),
+        dataset: target_config.name.to_string(),
+        branch: request.branch,
+        commit: commit.hash,
+        files,
+        timestamp: Utc::now(),
+    };
+    let mut promotions = get_promotions(pipeline_dataset_config, pipeline_hash)?;
+    promotions.push(promotion.clone());
+    store_promotions(pipeline_dataset_config, pipeline_hash, &promotions)?;
+    if let Err(error) = target_config.save_dataset(&vc_dataset) {
+        promotions.pop();
+        if let Err(rollback_error) = store_promotions(pipeline_dataset_config, pipeline_hash, &promotions) {
+            warn!(
+                "Could not remove promotion of pipeline execution {} into unpublished commit {}: {}",
+                pipeline_hash, promotion.commit, rollback_error
+            );
+        }
+        return Err(error);
+    }
+    datasets_ref.insert(target_config.name.to_string(), vc_dataset);
+    config.promotion_index.insert(&promotion)?;
+    Ok(promotion)
+}
+
+fn store_promotions(
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+    promotions: &[Promotion],
+) -> Result<(), DaemonError> {
+    dataset_config.backend.store_pipeline_document(
+        &dataset_config.name,
+        pipeline_hash,
+        PROMOTIONS_DOCUMENT,
+        &promotions,
+    )
+}
+
+/// Retrieves the commits into which results of a pipeline execution were promoted.
+#[get("/pipelines/{pipeline_hash}/promotions")]
+async fn get_pipeline_promotions(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!("Getting promotions of pipeline execution {}", pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let promotions = get_promotions(&dataset_config, &pipeline_hash)?;
+
+    Ok(HttpResponse::Ok().json(&promotions))
+}
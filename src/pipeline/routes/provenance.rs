@@ -1,12 +1,123 @@
 //! Contains routes with regards to provenance tracking for pipelines
 use super::helpers::find_dataset_conf_for_pipeline_hash;
 use crate::config;
+use crate::dataset::history;
 use crate::dataset::models::DatasetConfig;
 use crate::error::DaemonError;
+use crate::openlineage::EventType;
+use crate::pipeline::documents::{get_execution_status, get_result_metadata};
+use crate::pipeline::lineage::{self, BackwardTrace, ImpactReport, LineageGraph, RunImpact};
+use crate::pipeline::models::{BatchItemError, BatchLineageReport, PipelineStatus, STATUS_DOCUMENT};
+use crate::pipeline::prov::ProvBundle;
+use crate::pipeline::validation::{storage_order, LineageValidator};
 use actix_web::{get, post, web, HttpResponse};
+use futures::StreamExt;
+use iterum_rust::pipeline::PipelineExecution;
 use iterum_rust::provenance::FragmentLineage;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
 
-/// Creates a new FragmentLineage on the storage backend
+/// Query parameters to select the format in which lineage is returned.
+#[derive(Deserialize, Debug)]
+pub struct FormatOptions {
+    format: Option<String>,
+}
+
+/// Retrieves the pipeline execution lineage is posted for, together with a validator for the lineage. Lineage for unknown pipeline executions is rejected.
+fn find_pipeline_execution(
+    config: &config::Config,
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+) -> Result<(PipelineExecution, LineageValidator), DaemonError> {
+    let pipeline_execution = match dataset_config
+        .backend
+        .get_pipeline_execution(&dataset_config.name, pipeline_hash)
+    {
+        Ok(pipeline_execution) => pipeline_execution,
+        Err(DaemonError::NotFound) => {
+            return Err(DaemonError::BadRequest(format!(
+                "Pipeline execution {} does not exist.",
+                pipeline_hash
+            )))
+        }
+        Err(error) => return Err(error),
+    };
+    let validator = LineageValidator::new(&pipeline_execution);
+    validator.input_commit(&config.datasets.read().unwrap())?;
+    Ok((pipeline_execution, validator))
+}
+
+/// Moves a pending pipeline execution to running once lineage is posted for it, as it has evidently started. The OpenLineage RUNNING event is only emitted
+/// for this transition, so it is emitted once per pipeline execution instead of for every lineage post.
+fn start_on_lineage(
+    config: &config::Config,
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+    pipeline_execution: &PipelineExecution,
+) -> Result<(), DaemonError> {
+    // Acquire write lock, so concurrent lineage posts cannot both transition the pipeline execution.
+    {
+        let _datasets_ref = config.datasets.write().unwrap();
+        let mut status = get_execution_status(dataset_config, pipeline_hash)?;
+        if status.status != PipelineStatus::Pending {
+            return Ok(());
+        }
+        status.transition(PipelineStatus::Running)?;
+        dataset_config.backend.store_pipeline_document(
+            &dataset_config.name,
+            pipeline_hash,
+            STATUS_DOCUMENT,
+            &status,
+        )?;
+    }
+    if let Some(openlineage) = &config.openlineage {
+        openlineage.emit_for(EventType::Running, pipeline_execution);
+    }
+    Ok(())
+}
+
+/// Validates a FragmentLineage, stores it on the storage backend, and adds it to the lineage index.
+/// Only the predecessors the fragment names are retrieved from the storage backend, and only when they were not seen before by the validator.
+fn store_lineage(
+    config: &config::Config,
+    dataset_config: &DatasetConfig,
+    pipeline_hash: &str,
+    pipeline_execution: &PipelineExecution,
+    validator: &mut LineageValidator,
+    fragment_lineage: &FragmentLineage,
+) -> Result<(), DaemonError> {
+    // Acquire read lock, so the input commit is available while the fragment is validated and indexed.
+    let datasets = config.datasets.read().unwrap();
+    validator.validate(&datasets, fragment_lineage, |fragment_id| {
+        match dataset_config
+            .backend
+            .get_pipeline_fragment_lineage(dataset_config, pipeline_hash, fragment_id)
+        {
+            Ok(predecessor) => Ok(Some(predecessor)),
+            Err(DaemonError::NotFound) => Ok(None),
+            Err(error) => Err(error),
+        }
+    })?;
+    dataset_config.backend.store_pipeline_fragment_lineage(
+        dataset_config,
+        pipeline_hash,
+        fragment_lineage,
+        &config.pipeline_index,
+    )?;
+    let vc_dataset = datasets.get(&dataset_config.name).ok_or(DaemonError::NotFound)?;
+    config.lineage_index.insert(
+        &dataset_config.name,
+        vc_dataset,
+        &pipeline_execution.pipeline_run.input_dataset_commit_hash,
+        pipeline_hash,
+        fragment_lineage,
+    )?;
+    validator.record(fragment_lineage);
+    Ok(())
+}
+
+/// Creates a new FragmentLineage on the storage backend. The lineage is validated against the pipeline execution first, and rejected when it does not fit.
 #[post("/{dataset}/pipelines/{pipeline_hash}/lineage")]
 async fn post_fragment_lineage(
     config: web::Data<config::Config>,
@@ -24,33 +135,248 @@ async fn post_fragment_lineage(
         .ok_or_else(|| DaemonError::NotFound)?
         .into();
 
-    dataset_config
-        .backend
-        .store_pipeline_fragment_lineage(&dataset_config, &pipeline_hash, &fragment_lineage)?;
+    let (pipeline_execution, mut validator) = find_pipeline_execution(&config, &dataset_config, &pipeline_hash)?;
+    store_lineage(
+        &config,
+        &dataset_config,
+        &pipeline_hash,
+        &pipeline_execution,
+        &mut validator,
+        &fragment_lineage,
+    )?;
+    start_on_lineage(&config, &dataset_config, &pipeline_hash, &pipeline_execution)?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
-/// Retrieves a list of FragmentLineages from the storage backend
+/// Largest size in bytes of a batch of FragmentLineages, when `LINEAGE_BATCH_LIMIT_BYTES` is not set.
+const DEFAULT_LINEAGE_BATCH_LIMIT_BYTES: usize = 256 * 1024 * 1024;
+
+/// Reads the largest size of a batch of FragmentLineages from `LINEAGE_BATCH_LIMIT_BYTES`.
+fn batch_limit() -> usize {
+    env::var("LINEAGE_BATCH_LIMIT_BYTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_LINEAGE_BATCH_LIMIT_BYTES)
+}
+
+/// Creates many FragmentLineages on the storage backend in a single request. The body is either a JSON array of FragmentLineages, or newline-delimited JSON with one FragmentLineage per line.
+/// Every item is handled separately, so invalid items do not prevent the other items from being stored. The response reports which items could not be stored, and why.
+/// Batches larger than `LINEAGE_BATCH_LIMIT_BYTES` are refused. Items do not have to be ordered within the batch: fragments whose predecessors are part of the batch are stored after those predecessors.
+#[post("/{dataset}/pipelines/{pipeline_hash}/lineage/batch")]
+async fn post_fragment_lineage_batch(
+    config: web::Data<config::Config>,
+    path: web::Path<(String, String)>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, DaemonError> {
+    let (dataset_path, pipeline_hash) = path.into_inner();
+    info!(
+        "Posting batch of fragment lineages for pipeline execution {}",
+        pipeline_hash
+    );
+
+    let dataset_config: DatasetConfig = config
+        .local_config
+        .get(&dataset_path)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    let limit = batch_limit();
+    let mut body: Vec<u8> = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > limit {
+            return Err(DaemonError::BadRequest(format!(
+                "Batch exceeds the limit of {} bytes, split it into smaller batches.",
+                limit
+            )));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = String::from_utf8(body).map_err(|_| DaemonError::BadRequest("Body is not valid UTF-8.".to_owned()))?;
+
+    // A JSON array is parsed as a whole, otherwise every non-empty line is parsed as a separate item.
+    let items: Vec<Result<serde_json::Value, String>> = if body.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<serde_json::Value>>(&body)
+            .map_err(|error| DaemonError::BadRequest(format!("Body is not a valid JSON array: {}", error)))?
+            .into_iter()
+            .map(Ok)
+            .collect()
+    } else {
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|error| error.to_string()))
+            .collect()
+    };
+
+    let (pipeline_execution, mut validator) = find_pipeline_execution(&config, &dataset_config, &pipeline_hash)?;
+    let mut report = BatchLineageReport::default();
+    let mut pending: Vec<(usize, FragmentLineage)> = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        match item.and_then(|value| serde_json::from_value(value).map_err(|error| error.to_string())) {
+            Ok(fragment_lineage) => pending.push((index, fragment_lineage)),
+            Err(message) => report.errors.push(BatchItemError { index, message }),
+        }
+    }
+
+    let (indexes, fragment_lineages): (Vec<usize>, Vec<FragmentLineage>) = pending.into_iter().unzip();
+    for position in storage_order(&fragment_lineages) {
+        match store_lineage(
+            &config,
+            &dataset_config,
+            &pipeline_hash,
+            &pipeline_execution,
+            &mut validator,
+            &fragment_lineages[position],
+        ) {
+            Ok(()) => report.stored += 1,
+            Err(error) => report.errors.push(BatchItemError {
+                index: indexes[position],
+                message: error.to_string(),
+            }),
+        }
+    }
+    report.errors.sort_by_key(|error| error.index);
+    report.failed = report.errors.len();
+    debug!("Stored {} fragment lineages, {} failed.", report.stored, report.failed);
+
+    if report.stored > 0 {
+        start_on_lineage(&config, &dataset_config, &pipeline_hash, &pipeline_execution)?;
+    }
+
+    Ok(HttpResponse::Ok().json(&report))
+}
+
+/// Retrieves a list of FragmentLineages from the storage backend. With `format=prov-json` or `format=prov-n`, the provenance of the pipeline execution is returned
+/// in the W3C PROV data model instead.
 #[get("/pipelines/{pipeline_hash}/lineage")]
 async fn get_fragment_lineages(
     config: web::Data<config::Config>,
     path: web::Path<String>,
+    options: web::Query<FormatOptions>,
 ) -> Result<HttpResponse, DaemonError> {
     info!("Retrieving fragment lineages");
 
     let pipeline_hash = path.into_inner();
 
-    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config.local_config, &pipeline_hash) {
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let format = match options.format.as_deref() {
+        None => {
+            let fragment_lineages = dataset_config
+                .backend
+                .get_pipeline_fragment_lineages(&dataset_config, &pipeline_hash)?;
+            return Ok(HttpResponse::Ok().json(fragment_lineages));
+        }
+        Some(format) => format,
+    };
+
+    let pipeline_execution = dataset_config
+        .backend
+        .get_pipeline_execution(&dataset_config.name, &pipeline_hash)?;
+    let bundle = ProvBundle::build(
+        &pipeline_execution,
+        &lineage::load_lineages(&dataset_config, &pipeline_hash)?,
+    );
+
+    match format {
+        "prov-json" => Ok(HttpResponse::Ok().json(bundle.to_prov_json())),
+        "prov-n" => Ok(HttpResponse::Ok()
+            .content_type("text/provenance-notation")
+            .body(bundle.to_prov_n())),
+        format => Err(DaemonError::BadRequest(format!("Unknown lineage format {}.", format))),
+    }
+}
+
+/// Retrieves the full provenance graph of a pipeline execution, assembled from all of its FragmentLineages.
+/// The graph is returned as JSON nodes and edges, or in the Graphviz DOT language when `format=dot` is given.
+/// This route is registered before the route of a single FragmentLineage, so it takes precedence over a fragment named `graph`.
+#[get("/pipelines/{pipeline_hash}/lineage/graph")]
+async fn get_lineage_graph(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    options: web::Query<FormatOptions>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!("Retrieving lineage graph of pipeline execution {}", pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let pipeline_execution = dataset_config
+        .backend
+        .get_pipeline_execution(&dataset_config.name, &pipeline_hash)?;
+    let graph = LineageGraph::build(
+        &pipeline_execution.pipeline_run.input_dataset_commit_hash,
+        &lineage::load_lineages(&dataset_config, &pipeline_hash)?,
+    );
+
+    match options.format.as_deref() {
+        None | Some("json") => Ok(HttpResponse::Ok().json(&graph)),
+        Some("dot") => Ok(HttpResponse::Ok()
+            .content_type("text/vnd.graphviz")
+            .body(graph.to_dot())),
+        Some(format) => Err(DaemonError::BadRequest(format!("Unknown lineage format {}.", format))),
+    }
+}
+
+/// Traces a result file of a pipeline execution back to the files of the input dataset it was derived from, by walking through the stored FragmentLineages.
+/// Returns the input files together with the dataset and commit they came from, and the fragments and transformation steps in between.
+#[get("/pipelines/{pipeline_hash}/trace/{filename:.*}")]
+async fn trace_result(
+    config: web::Data<config::Config>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, DaemonError> {
+    let (pipeline_hash, filename) = path.into_inner();
+    info!("Tracing result {} of pipeline execution {}", filename, pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
         Some(conf) => conf,
         None => return Ok(HttpResponse::NotFound().finish()),
     };
 
-    let fragment_lineages = dataset_config
+    let pipeline_execution = dataset_config
         .backend
-        .get_pipeline_fragment_lineages(&dataset_config, &pipeline_hash)?;
+        .get_pipeline_execution(&dataset_config.name, &pipeline_hash)?;
+    let lineages = lineage::load_lineages(&dataset_config, &pipeline_hash)?;
+    let (inputs, fragments, steps) = lineage::trace_backward(&lineages, &filename).ok_or(DaemonError::NotFound)?;
+
+    // The input commit contains promoted results when it, or one of its ancestors, is a promotion commit. The closest promotion is reported.
+    let pipeline_run = pipeline_execution.pipeline_run;
+    let commits = {
+        let datasets = config.datasets.read().unwrap();
+        match datasets.get(&pipeline_run.input_dataset) {
+            Some(vc_dataset) => history::commit_chain(vc_dataset, &pipeline_run.input_dataset_commit_hash)?,
+            None => Vec::new(),
+        }
+    };
+    let mut promoted_from = None;
+    for commit in commits.iter().rev() {
+        // Promotions of pipeline executions which were deleted since are not reported.
+        if let Some(source) = config.promotion_index.get(&pipeline_run.input_dataset, &commit.hash)? {
+            if config.pipeline_index.get(&source)?.is_some() {
+                promoted_from = Some(source);
+                break;
+            }
+        }
+    }
+
+    let trace = BackwardTrace {
+        result: filename,
+        dataset: pipeline_run.input_dataset,
+        commit: pipeline_run.input_dataset_commit_hash,
+        promoted_from,
+        inputs,
+        fragments,
+        steps,
+    };
 
-    Ok(HttpResponse::Ok().json(fragment_lineages))
+    Ok(HttpResponse::Ok().json(&trace))
 }
 
 /// Retrieves a specific FragmentLineage from the storage backend
@@ -63,7 +389,7 @@ async fn get_fragment_lineage(
 
     let (pipeline_hash, fragment_id) = path.into_inner();
 
-    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config.local_config, &pipeline_hash) {
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
         Some(conf) => conf,
         None => return Ok(HttpResponse::NotFound().finish()),
     };
@@ -75,3 +401,61 @@ async fn get_fragment_lineage(
 
     Ok(HttpResponse::Ok().json(fragment_lineage))
 }
+
+/// Lists every pipeline execution, fragment and result file which depends on a file at a specific commit of a dataset, using the lineage index.
+/// Pipeline executions which read the same version of the file from another commit are included as well.
+#[get("/{dataset}/impact/{commit}/{filename:.*}")]
+async fn get_file_impact(
+    config: web::Data<config::Config>,
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, DaemonError> {
+    let (dataset_path, commit_hash, filename) = path.into_inner();
+    info!(
+        "Retrieving impact of file {} at commit {} of dataset {}",
+        filename, commit_hash, dataset_path
+    );
+
+    let dataset_config: DatasetConfig = config
+        .local_config
+        .get(&dataset_path)?
+        .ok_or_else(|| DaemonError::NotFound)?
+        .into();
+
+    // Group the fragments which consumed the file by pipeline execution.
+    let mut usages: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    {
+        let datasets = config.datasets.read().unwrap();
+        let vc_dataset = datasets.get(&dataset_path).ok_or(DaemonError::NotFound)?;
+        for usage in config
+            .lineage_index
+            .find(&dataset_path, vc_dataset, &commit_hash, &filename)?
+        {
+            usages.entry(usage.pipeline_hash).or_default().push(usage.fragment_id);
+        }
+    }
+
+    let mut runs: Vec<RunImpact> = Vec::new();
+    for (pipeline_hash, fragment_ids) in usages {
+        let lineages = lineage::load_lineages(&dataset_config, &pipeline_hash)?;
+        let (fragments, derived_files) = lineage::trace_forward(&lineages, &fragment_ids);
+        let results = lineage::derived_results(
+            &get_result_metadata(&dataset_config, &pipeline_hash)?,
+            &fragments,
+            &derived_files,
+        );
+        runs.push(RunImpact {
+            pipeline_hash,
+            fragments,
+            results,
+        });
+    }
+
+    let report = ImpactReport {
+        dataset: dataset_path,
+        commit: commit_hash,
+        file: filename,
+        runs,
+    };
+
+    Ok(HttpResponse::Ok().json(&report))
+}
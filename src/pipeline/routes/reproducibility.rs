@@ -0,0 +1,55 @@
+//! Contains routes with regards to verifying the reproducibility of pipeline executions
+use super::helpers::find_dataset_conf_for_pipeline_hash;
+use crate::config;
+use crate::error::DaemonError;
+use crate::pipeline::models::REPRODUCIBILITY_DOCUMENT;
+use crate::pipeline::reproducibility::{self, ReproducibilityReport};
+use actix_web::{get, post, web, HttpResponse};
+
+/// Verifies whether other pipeline executions of the same pipeline definition on the same input commit produced byte-identical results.
+/// The pipeline execution is flagged as reproducible or divergent, which is stored next to it.
+#[post("/pipelines/{pipeline_hash}/reproducibility")]
+async fn verify_reproducibility(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!("Verifying reproducibility of pipeline execution {}", pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let report = reproducibility::verify(&config, &dataset_config, &pipeline_hash)?;
+    dataset_config.backend.store_pipeline_document(
+        &dataset_config.name,
+        &pipeline_hash,
+        REPRODUCIBILITY_DOCUMENT,
+        &report,
+    )?;
+
+    Ok(HttpResponse::Ok().json(&report))
+}
+
+/// Retrieves the outcome of the last reproducibility verification of a pipeline execution
+#[get("/pipelines/{pipeline_hash}/reproducibility")]
+async fn get_reproducibility(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!("Getting reproducibility of pipeline execution {}", pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let report: ReproducibilityReport =
+        dataset_config
+            .backend
+            .get_pipeline_document(&dataset_config.name, &pipeline_hash, REPRODUCIBILITY_DOCUMENT)?;
+
+    Ok(HttpResponse::Ok().json(&report))
+}
@@ -3,21 +3,43 @@ use super::helpers::find_dataset_conf_for_pipeline_hash;
 use crate::config;
 use crate::dataset::models::DatasetConfig;
 use crate::error::DaemonError;
+use crate::pipeline::documents::{get_result_metadata, get_stored_result_metadata};
+use crate::pipeline::lineage;
+use crate::pipeline::models::{guess_mime_type, ResultListing, ResultMetadata, ResultOrigin, RESULTS_DOCUMENT};
+use crate::utils as daemon_utils;
 use actix_multipart::Multipart;
 use actix_web::{get, post, web, HttpResponse};
 use async_std::prelude::*;
 use futures::StreamExt;
 use iterum_rust::utils;
-use std::ffi::OsStr;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
+/// Query parameters for downloading the results of a pipeline execution as an archive.
+#[derive(Deserialize, Debug)]
+pub struct ArchiveOptions {
+    #[serde(default)]
+    include_provenance: bool,
+}
+
+/// Query parameters for listing the results of a pipeline execution.
+#[derive(Deserialize, Debug)]
+pub struct ResultListOptions {
+    /// Whether to return the metadata of every result, instead of only its name.
+    #[serde(default)]
+    details: bool,
+}
+
 /// Creates a new results for a pipeline, and stores it on the storage backend
 /// First stores the data in a temporary folder. Then redirects the data to the storage backend.
+/// File names may contain slashes to store results in subdirectories. The step and fragment which produced the results can be passed as query parameters.
 #[post("/{dataset}/pipeline_result/{pipeline_hash}")]
 async fn add_result(
     config: web::Data<config::Config>,
     path: web::Path<(String, String)>,
+    origin: web::Query<ResultOrigin>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, DaemonError> {
     let (dataset_path, pipeline_hash) = path.into_inner();
@@ -45,9 +67,20 @@ async fn add_result(
             .get_filename()
             .ok_or_else(|| actix_web::error::ParseError::Incomplete)?;
 
+        if !daemon_utils::is_contained_path(filename) {
+            std::fs::remove_dir_all(&temp_path)?;
+            return Err(DaemonError::BadRequest(format!(
+                "Invalid result file name {}",
+                filename
+            )));
+        }
+
         let filepath = format!("{}{}", &temp_path, &filename);
         debug!("Saving file to {}", filepath);
         debug!("Filename: {}", filename);
+        if let Some(parent_path) = Path::new(&filepath).parent() {
+            fs::create_dir_all(parent_path)?;
+        }
 
         let mut f = async_std::fs::File::create(&filepath).await?;
         // Field in turn is stream of *Bytes* object
@@ -62,53 +95,133 @@ async fn add_result(
     // Acquire write lock
     {
         let _datasets_ref = config.datasets.write().unwrap();
-        dataset_config.store_pipeline_result_files(&file_list, &pipeline_hash, &temp_path.to_string())?;
+        dataset_config.store_pipeline_result_files(
+            &file_list,
+            &pipeline_hash,
+            &temp_path.to_string(),
+            &config.pipeline_index,
+        )?;
+        let mut results = get_stored_result_metadata(&dataset_config, &pipeline_hash)?;
+        for (filename, filepath) in &file_list {
+            let mut metadata = ResultMetadata::from_file(Path::new(filepath), filename)?;
+            metadata.step = origin.step.clone();
+            metadata.fragment = origin.fragment.clone();
+            results.insert(filename.to_string(), metadata);
+        }
+        dataset_config.backend.store_pipeline_document(
+            &dataset_config.name,
+            &pipeline_hash,
+            RESULTS_DOCUMENT,
+            &results,
+        )?;
         std::fs::remove_dir_all(&temp_path)?;
     }
 
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Download all results of a pipeline as a gzipped tarball, in which the results are placed under `results/`. The tarball is streamed while it is being generated.
+/// With `?include_provenance=true`, the execution.json and the lineage of the pipeline execution are included as well.
+/// This route is registered before the route of a single result, so it takes precedence over a result named `archive`.
+#[get("/pipelines/{pipeline_hash}/results/archive")]
+async fn get_pipeline_results_archive(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    options: web::Query<ArchiveOptions>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!("Getting archive of results of pipeline {}", pipeline_hash);
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let include_provenance = options.include_provenance;
+    let archived_hash = pipeline_hash.to_string();
+    let archive = daemon_utils::stream_writer(move |writer| {
+        dataset_config
+            .backend
+            .export_pipeline_results(&dataset_config, &archived_hash, include_provenance, writer)
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/gzip")
+        .header(
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.tar.gz\"", pipeline_hash),
+        )
+        .streaming(archive))
+}
+
 /// Get specific result from a pipeline
-#[get("/pipelines/{pipeline_hash}/results/{filename}")]
+#[get("/pipelines/{pipeline_hash}/results/{filename:.*}")]
 async fn get_pipeline_result(
     config: web::Data<config::Config>,
     path: web::Path<(String, String)>,
 ) -> Result<HttpResponse, DaemonError> {
     let (pipeline_hash, file_name) = path.into_inner();
     info!("Getting pipeline result {}:{}", pipeline_hash, file_name);
-    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config.local_config, &pipeline_hash) {
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
         Some(conf) => conf,
         None => return Ok(HttpResponse::NotFound().finish()),
     };
+    if !daemon_utils::is_contained_path(&file_name) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
     let pipeline_result: Vec<u8> = dataset_config.get_pipeline_result(&pipeline_hash, &file_name)?;
 
-    let file_path = Path::new(&file_name);
-    let response = match file_path
-        .extension()
-        .and_then(OsStr::to_str)
-        .expect("Something wrong with the file")
-    {
-        "jpg" => HttpResponse::Ok().content_type("image/jpeg").body(pipeline_result),
-        _ => HttpResponse::Ok().body(pipeline_result),
-    };
-
-    Ok(response)
+    Ok(HttpResponse::Ok()
+        .content_type(guess_mime_type(&file_name))
+        .body(pipeline_result))
 }
 
-/// Get list of results for a pipeline
+/// Get list of results for a pipeline. With `?details=true`, every result is listed with its size, content hash, mime type, upload time,
+/// and the step and fragment which produced it. When these were not passed on upload, they are taken from the lineage of the pipeline execution.
 #[get("/pipelines/{pipeline_hash}/results")]
 async fn get_pipeline_results(
     config: web::Data<config::Config>,
     path: web::Path<String>,
+    options: web::Query<ResultListOptions>,
 ) -> Result<HttpResponse, DaemonError> {
     let pipeline_hash = path.into_inner();
     info!("Getting pipeline result {}", pipeline_hash);
-    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config.local_config, &pipeline_hash) {
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
         Some(conf) => conf,
         None => return Ok(HttpResponse::NotFound().finish()),
     };
-    let pipeline_result = dataset_config.get_pipeline_results(&pipeline_hash)?;
+    if !options.details {
+        let pipeline_result = dataset_config.get_pipeline_results(&pipeline_hash)?;
+        return Ok(HttpResponse::Ok().json(pipeline_result));
+    }
+
+    // Fragments produced by a transformation step contain its output files.
+    let lineages = lineage::load_lineages(&dataset_config, &pipeline_hash)?;
+    let mut producers: HashMap<&str, (&str, &str)> = HashMap::new();
+    for fragment in lineages
+        .iter()
+        .filter(|fragment| !fragment.description.metadata.predecessors.is_empty())
+    {
+        for file in &fragment.description.files {
+            producers.insert(
+                file.name.as_str(),
+                (
+                    fragment.description.metadata.fragment_id.as_str(),
+                    fragment.transformation_step.as_str(),
+                ),
+            );
+        }
+    }
+
+    let listings: Vec<ResultListing> = get_result_metadata(&dataset_config, &pipeline_hash)?
+        .into_iter()
+        .map(|(name, mut metadata)| {
+            if let Some((fragment, step)) = producers.get(name.as_str()) {
+                metadata.fragment = metadata.fragment.or_else(|| Some(fragment.to_string()));
+                metadata.step = metadata.step.or_else(|| Some(step.to_string()));
+            }
+            ResultListing { name, metadata }
+        })
+        .collect();
 
-    Ok(HttpResponse::Ok().json(pipeline_result))
+    Ok(HttpResponse::Ok().json(listings))
 }
@@ -0,0 +1,30 @@
+//! Contains routes with regards to statistics of a pipeline execution
+use super::helpers::find_dataset_conf_for_pipeline_hash;
+use crate::config;
+use crate::error::DaemonError;
+use crate::pipeline::documents::get_result_metadata;
+use crate::pipeline::lineage;
+use crate::pipeline::stats;
+use actix_web::{get, web, HttpResponse};
+
+/// Retrieve statistics per transformation step of a pipeline execution, such as the number of fragments and files it handled.
+/// When the sidecars supplied timestamps, the duration percentiles and throughput of each step are included as well.
+#[get("/pipelines/{pipeline_hash}/stats")]
+async fn get_pipeline_stats(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!("Getting statistics of pipeline execution {}", pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let lineages = lineage::load_lineages(&dataset_config, &pipeline_hash)?;
+    let results = get_result_metadata(&dataset_config, &pipeline_hash)?;
+    let pipeline_stats = stats::compute_stats(&pipeline_hash, &lineages, &results);
+
+    Ok(HttpResponse::Ok().json(&pipeline_stats))
+}
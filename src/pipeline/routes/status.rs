@@ -0,0 +1,72 @@
+//! Contains routes with regards to the status of a pipeline execution. Status updates have to follow the state machine defined by `PipelineStatus`.
+use super::helpers::find_dataset_conf_for_pipeline_hash;
+use crate::config;
+use crate::error::DaemonError;
+use crate::openlineage::EventType;
+use crate::pipeline::documents::get_execution_status;
+use crate::pipeline::models::{StatusUpdate, STATUS_DOCUMENT};
+use actix_web::{get, patch, web, HttpResponse};
+
+/// Retrieve the status of a pipeline execution, including the history of status transitions
+#[get("/pipelines/{pipeline_hash}/status")]
+async fn get_pipeline_status(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!("Getting status of pipeline execution {}", pipeline_hash);
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let status = get_execution_status(&dataset_config, &pipeline_hash)?;
+
+    Ok(HttpResponse::Ok().json(&status))
+}
+
+/// Update the status of a pipeline execution. Invalid transitions, such as moving a finished pipeline execution back to running, are rejected.
+#[patch("/pipelines/{pipeline_hash}/status")]
+async fn update_pipeline_status(
+    config: web::Data<config::Config>,
+    path: web::Path<String>,
+    update: web::Json<StatusUpdate>,
+) -> Result<HttpResponse, DaemonError> {
+    let pipeline_hash = path.into_inner();
+    info!(
+        "Updating status of pipeline execution {} to {}",
+        pipeline_hash, update.status
+    );
+
+    let dataset_config = match find_dataset_conf_for_pipeline_hash(&config, &pipeline_hash)? {
+        Some(conf) => conf,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    // Acquire write lock, so concurrent updates cannot both transition from the same state.
+    let status = {
+        let _datasets_ref = config.datasets.write().unwrap();
+        let mut status = get_execution_status(&dataset_config, &pipeline_hash)?;
+        status.transition(update.status)?;
+        dataset_config.backend.store_pipeline_document(
+            &dataset_config.name,
+            &pipeline_hash,
+            STATUS_DOCUMENT,
+            &status,
+        )?;
+        status
+    };
+
+    if let (Some(openlineage), Some(event_type)) = (&config.openlineage, EventType::for_status(status.status)) {
+        match dataset_config
+            .backend
+            .get_pipeline_execution(&dataset_config.name, &pipeline_hash)
+        {
+            Ok(pipeline_execution) => openlineage.emit_for(event_type, &pipeline_execution),
+            Err(error) => warn!("Could not emit OpenLineage event for {}: {}", pipeline_hash, error),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(&status))
+}
@@ -0,0 +1,264 @@
+//! Contains the computation of statistics per transformation step of a pipeline execution, from its lineage. Timing statistics are only available
+//! when the sidecars supply timestamps in the custom metadata of fragments, as `started_at` and `finished_at` in RFC 3339 format.
+use crate::pipeline::models::ResultMetadata;
+use chrono::{DateTime, Utc};
+use iterum_rust::provenance::FragmentLineage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Percentiles of the time it took a transformation step to handle a fragment, in milliseconds.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DurationStats {
+    /// Number of fragments for which timestamps were available.
+    pub samples: usize,
+    pub min_ms: i64,
+    pub p50_ms: i64,
+    pub p90_ms: i64,
+    pub p99_ms: i64,
+    pub max_ms: i64,
+    pub mean_ms: f64,
+}
+
+/// Statistics of a single transformation step of a pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StepStats {
+    pub name: String,
+    pub fragments: usize,
+    pub input_files: usize,
+    pub output_files: usize,
+    /// Total size of the result files of the pipeline execution which were produced by the step. Each result file is counted once.
+    pub result_bytes: u64,
+    pub durations: Option<DurationStats>,
+    /// Fragments handled per second, between the first fragment starting and the last fragment finishing.
+    pub fragments_per_second: Option<f64>,
+}
+
+/// Statistics of all transformation steps of a pipeline execution.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PipelineStats {
+    pub pipeline_hash: String,
+    pub fragments: usize,
+    pub steps: Vec<StepStats>,
+}
+
+/// Statistics of a transformation step while the lineage is being aggregated.
+#[derive(Default)]
+struct StepAccumulator {
+    stats: StepStats,
+    durations: Vec<i64>,
+    /// Start of the first fragment and end of the last fragment handled by the step.
+    span: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+/// Reads the timestamps a sidecar supplied for a fragment, if any.
+fn fragment_timestamps(lineage: &FragmentLineage) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let custom = lineage.description.metadata.custom.as_ref()?;
+    let timestamp = |key: &str| -> Option<DateTime<Utc>> {
+        match custom.get(key)? {
+            Value::String(timestamp) => DateTime::parse_from_rfc3339(timestamp)
+                .ok()
+                .map(|timestamp| timestamp.with_timezone(&Utc)),
+            _ => None,
+        }
+    };
+    let started_at = timestamp("started_at")?;
+    let finished_at = timestamp("finished_at")?;
+    if finished_at < started_at {
+        return None;
+    }
+    Some((started_at, finished_at))
+}
+
+/// Determines a percentile of sorted durations, using the nearest-rank method.
+fn percentile(sorted: &[i64], percent: usize) -> i64 {
+    let rank = (percent * sorted.len() + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+fn duration_stats(mut durations: Vec<i64>) -> Option<DurationStats> {
+    if durations.is_empty() {
+        return None;
+    }
+    durations.sort();
+    Some(DurationStats {
+        samples: durations.len(),
+        min_ms: durations[0],
+        p50_ms: percentile(&durations, 50),
+        p90_ms: percentile(&durations, 90),
+        p99_ms: percentile(&durations, 99),
+        max_ms: durations[durations.len() - 1],
+        mean_ms: durations.iter().sum::<i64>() as f64 / durations.len() as f64,
+    })
+}
+
+/// Determines the transformation step which produced a result file. Results stored without a step or fragment are attributed to
+/// the step of the last fragment in the lineage with an output file of the same name.
+fn result_step<'a>(
+    file_name: &str,
+    result: &'a ResultMetadata,
+    fragments_by_id: &HashMap<&str, &'a FragmentLineage>,
+    producers: &HashMap<&str, &'a str>,
+) -> Option<&'a str> {
+    if let Some(step) = &result.step {
+        return Some(step);
+    }
+    if let Some(fragment) = &result.fragment {
+        return fragments_by_id
+            .get(fragment.as_str())
+            .map(|lineage| lineage.transformation_step.as_str());
+    }
+    producers.get(file_name).copied()
+}
+
+/// Aggregates the lineage of a pipeline execution per transformation step. Steps are ordered on the first time they occur in the lineage.
+/// Fragments without predecessors were created by the fragmenter, and their files are both its input and its output.
+pub fn compute_stats(
+    pipeline_hash: &str,
+    lineages: &[FragmentLineage],
+    results: &BTreeMap<String, ResultMetadata>,
+) -> PipelineStats {
+    let fragments_by_id: HashMap<&str, &FragmentLineage> = lineages
+        .iter()
+        .map(|lineage| (lineage.description.metadata.fragment_id.as_str(), lineage))
+        .collect();
+
+    let mut order: Vec<&str> = Vec::new();
+    let mut steps: HashMap<&str, StepAccumulator> = HashMap::new();
+    // Step of the last fragment with an output file of each name.
+    let mut producers: HashMap<&str, &str> = HashMap::new();
+    for lineage in lineages {
+        let name = lineage.transformation_step.as_str();
+        if !steps.contains_key(name) {
+            order.push(name);
+        }
+        let step = steps.entry(name).or_insert_with(|| StepAccumulator {
+            stats: StepStats {
+                name: name.to_owned(),
+                ..StepStats::default()
+            },
+            ..StepAccumulator::default()
+        });
+
+        let metadata = &lineage.description.metadata;
+        let outputs: BTreeSet<&str> = lineage
+            .description
+            .files
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect();
+        let inputs: BTreeSet<&str> = if metadata.predecessors.is_empty() {
+            outputs.clone()
+        } else {
+            metadata
+                .predecessors
+                .iter()
+                .filter_map(|predecessor| fragments_by_id.get(predecessor.as_str()))
+                .flat_map(|predecessor| predecessor.description.files.iter().map(|file| file.name.as_str()))
+                .collect()
+        };
+
+        for output in &outputs {
+            producers.insert(*output, name);
+        }
+        step.stats.fragments += 1;
+        step.stats.input_files += inputs.len();
+        step.stats.output_files += outputs.len();
+
+        if let Some((started_at, finished_at)) = fragment_timestamps(lineage) {
+            step.durations.push((finished_at - started_at).num_milliseconds());
+            step.span = Some(match step.span {
+                Some((first, last)) => (first.min(started_at), last.max(finished_at)),
+                None => (started_at, finished_at),
+            });
+        }
+    }
+
+    for (file_name, result) in results {
+        if let Some(step) = result_step(file_name, result, &fragments_by_id, &producers) {
+            if let Some(step) = steps.get_mut(step) {
+                step.stats.result_bytes += result.size;
+            }
+        }
+    }
+
+    let steps = order
+        .into_iter()
+        .filter_map(|name| steps.remove(name))
+        .map(|step| {
+            let mut stats = step.stats;
+            stats.fragments_per_second = step
+                .span
+                .map(|(first, last)| (last - first).num_milliseconds())
+                .filter(|elapsed| *elapsed > 0)
+                .map(|elapsed| step.durations.len() as f64 * 1000.0 / elapsed as f64);
+            stats.durations = duration_stats(step.durations);
+            stats
+        })
+        .collect();
+
+    PipelineStats {
+        pipeline_hash: pipeline_hash.to_owned(),
+        fragments: lineages.len(),
+        steps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::lineage::tests::fragment_lineage;
+
+    fn result(size: u64, step: Option<&str>, fragment: Option<&str>) -> ResultMetadata {
+        ResultMetadata {
+            hash: String::new(),
+            size,
+            mime_type: String::new(),
+            uploaded_at: None,
+            step: step.map(str::to_owned),
+            fragment: fragment.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let durations: Vec<i64> = (1..=10).collect();
+        assert_eq!(percentile(&durations, 50), 5);
+        assert_eq!(percentile(&durations, 90), 9);
+        assert_eq!(percentile(&durations, 99), 10);
+        assert_eq!(percentile(&durations, 0), 1);
+        assert_eq!(percentile(&[7], 50), 7);
+    }
+
+    #[test]
+    fn duration_stats_of_no_samples_is_none() {
+        assert!(duration_stats(Vec::new()).is_none());
+        let stats = duration_stats(vec![30, 10, 20]).unwrap();
+        assert_eq!((stats.min_ms, stats.p50_ms, stats.max_ms), (10, 20, 30));
+        assert_eq!(stats.mean_ms, 20.0);
+    }
+
+    #[test]
+    fn result_bytes_are_counted_once_per_result() {
+        let lineages = vec![
+            fragment_lineage("f1", "fragmenter", &[], &["input.txt"]),
+            fragment_lineage("t1", "transform", &["f1"], &["out.txt"]),
+            fragment_lineage("t2", "transform", &["f1"], &["out.txt"]),
+            fragment_lineage("s1", "summarize", &["t1", "t2"], &["summary.txt"]),
+        ];
+        let mut results = BTreeMap::new();
+        results.insert("out.txt".to_owned(), result(100, None, None));
+        results.insert("t1/other.txt".to_owned(), result(10, None, Some("t1")));
+        results.insert("summary.txt".to_owned(), result(5, Some("summarize"), None));
+
+        let stats = compute_stats("hash", &lineages, &results);
+        let bytes: Vec<(&str, u64)> = stats
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), step.result_bytes))
+            .collect();
+        assert_eq!(bytes, vec![("fragmenter", 0), ("transform", 110), ("summarize", 5)]);
+        assert_eq!(stats.steps[1].fragments, 2);
+        assert_eq!(stats.steps[1].input_files, 2);
+    }
+}
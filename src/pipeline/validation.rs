@@ -0,0 +1,313 @@
+//! Contains the validation of lineage against the pipeline execution it is posted for, such that only lineage which fits the pipeline definition ends up in the provenance graph.
+//!
+//! Lineage has to be posted after the lineage of its predecessors, as fragments are checked against the outputs of their predecessors.
+//! Fragments whose predecessors are not stored yet are rejected, and can be posted again once the predecessors are stored.
+use crate::error::DaemonError;
+use iterum_rust::pipeline::PipelineExecution;
+use iterum_rust::provenance::FragmentLineage;
+use iterum_rust::vc::{Commit, Dataset};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Name of the transformation step which creates the fragments without predecessors, from files of the input commit.
+pub const FRAGMENTER_STEP: &str = "fragmenter";
+
+/// Key in the custom metadata of a fragment under which a sidecar can list the files the fragment was created from.
+const INPUT_FILES_KEY: &str = "input_files";
+
+/// Validates FragmentLineages of a single pipeline execution. The output files of predecessors are remembered,
+/// such that fragments sharing a predecessor do not look it up again.
+pub struct LineageValidator {
+    input_dataset: String,
+    input_commit: String,
+    steps: HashSet<String>,
+    fragments: HashMap<String, HashSet<String>>,
+}
+
+/// Reads the files a fragment was created from, when these are listed in its custom metadata.
+fn input_files(fragment_lineage: &FragmentLineage) -> Result<Vec<&str>, DaemonError> {
+    let listed = match &fragment_lineage.description.metadata.custom {
+        Some(custom) => custom.get(INPUT_FILES_KEY),
+        None => None,
+    };
+    match listed {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(files)) => files
+            .iter()
+            .map(|file| {
+                file.as_str().ok_or_else(|| {
+                    DaemonError::BadRequest(format!(
+                        "Input files of fragment {} are not a list of file names.",
+                        fragment_lineage.description.metadata.fragment_id
+                    ))
+                })
+            })
+            .collect(),
+        Some(_) => Err(DaemonError::BadRequest(format!(
+            "Input files of fragment {} are not a list of file names.",
+            fragment_lineage.description.metadata.fragment_id
+        ))),
+    }
+}
+
+impl LineageValidator {
+    /// Creates a validator for the lineage of a pipeline execution.
+    pub fn new(pipeline_execution: &PipelineExecution) -> LineageValidator {
+        let pipeline_run = &pipeline_execution.pipeline_run;
+        LineageValidator {
+            input_dataset: pipeline_run.input_dataset.to_string(),
+            input_commit: pipeline_run.input_dataset_commit_hash.to_string(),
+            steps: pipeline_run.steps.iter().map(|step| step.name.to_string()).collect(),
+            fragments: HashMap::new(),
+        }
+    }
+
+    /// Retrieves the input commit of the pipeline execution.
+    pub fn input_commit<'a>(&self, datasets: &'a HashMap<String, Dataset>) -> Result<&'a Commit, DaemonError> {
+        datasets
+            .get(&self.input_dataset)
+            .and_then(|vc_dataset| vc_dataset.commits.get(&self.input_commit))
+            .ok_or_else(|| {
+                DaemonError::BadRequest(format!(
+                    "Input commit {} of dataset {} does not exist.",
+                    self.input_commit, self.input_dataset
+                ))
+            })
+    }
+
+    /// Checks a FragmentLineage against the pipeline execution. Every fragment has to be created by the fragmenter or by one of the transformation steps of the pipeline.
+    /// Fragments without predecessors are created by the fragmenter, so their files have to be present in the input commit. Other fragments have to be created from
+    /// predecessors which are stored already, and the input files they list have to be outputs of those predecessors.
+    /// Predecessors are retrieved with `stored_fragment`, which returns `None` for fragments which are not stored.
+    pub fn validate<F>(
+        &mut self,
+        datasets: &HashMap<String, Dataset>,
+        fragment_lineage: &FragmentLineage,
+        mut stored_fragment: F,
+    ) -> Result<(), DaemonError>
+    where
+        F: FnMut(&str) -> Result<Option<FragmentLineage>, DaemonError>,
+    {
+        let metadata = &fragment_lineage.description.metadata;
+        let step = &fragment_lineage.transformation_step;
+        if step != FRAGMENTER_STEP && !self.steps.contains(step) {
+            return Err(DaemonError::BadRequest(format!(
+                "Transformation step {} of fragment {} is not part of the pipeline.",
+                step, metadata.fragment_id
+            )));
+        }
+
+        if metadata.predecessors.is_empty() {
+            let commit = self.input_commit(datasets)?;
+            if let Some(file) = fragment_lineage
+                .description
+                .files
+                .iter()
+                .find(|file| !commit.files.contains(&file.name))
+            {
+                return Err(DaemonError::BadRequest(format!(
+                    "File {} of fragment {} is not present in the input commit.",
+                    file.name, metadata.fragment_id
+                )));
+            }
+            return Ok(());
+        }
+
+        for predecessor in &metadata.predecessors {
+            if !self.fragments.contains_key(predecessor) {
+                let predecessor_lineage = stored_fragment(predecessor)?.ok_or_else(|| {
+                    DaemonError::BadRequest(format!(
+                        "Predecessor {} of fragment {} is not stored yet.",
+                        predecessor, metadata.fragment_id
+                    ))
+                })?;
+                self.record(&predecessor_lineage);
+            }
+        }
+        let predecessor_outputs: HashSet<&str> = metadata
+            .predecessors
+            .iter()
+            .flat_map(|predecessor| self.fragments[predecessor].iter().map(String::as_str))
+            .collect();
+        if let Some(file) = input_files(fragment_lineage)?
+            .into_iter()
+            .find(|file| !predecessor_outputs.contains(file))
+        {
+            return Err(DaemonError::BadRequest(format!(
+                "Input file {} of fragment {} is not an output of its predecessors.",
+                file, metadata.fragment_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Remembers the output files of a fragment which was stored, such that it is not looked up when it is referred to as a predecessor.
+    pub fn record(&mut self, fragment_lineage: &FragmentLineage) {
+        self.fragments.insert(
+            fragment_lineage.description.metadata.fragment_id.to_string(),
+            fragment_lineage
+                .description
+                .files
+                .iter()
+                .map(|file| file.name.to_string())
+                .collect(),
+        );
+    }
+}
+
+/// Determines the order in which a batch of FragmentLineages is stored, such that fragments are stored after their predecessors within the batch.
+/// Returns the positions of the fragments in the batch. The order is a topological sort which keeps the order of the batch where possible,
+/// and fragments which refer to each other in a cycle are placed last, in the order of the batch.
+pub fn storage_order(fragment_lineages: &[FragmentLineage]) -> Vec<usize> {
+    let mut positions: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (position, fragment_lineage) in fragment_lineages.iter().enumerate() {
+        positions
+            .entry(fragment_lineage.description.metadata.fragment_id.as_str())
+            .or_default()
+            .push(position);
+    }
+
+    // For every fragment, the number of fragments in the batch it waits for, and the fragments which wait for it.
+    let mut waiting_for = vec![0usize; fragment_lineages.len()];
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); fragment_lineages.len()];
+    for (position, fragment_lineage) in fragment_lineages.iter().enumerate() {
+        for predecessor in &fragment_lineage.description.metadata.predecessors {
+            for predecessor_position in positions.get(predecessor.as_str()).into_iter().flatten() {
+                waiting_for[position] += 1;
+                dependents[*predecessor_position].push(position);
+            }
+        }
+    }
+
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..fragment_lineages.len())
+        .filter(|position| waiting_for[*position] == 0)
+        .map(Reverse)
+        .collect();
+    let mut order = Vec::with_capacity(fragment_lineages.len());
+    while let Some(Reverse(position)) = ready.pop() {
+        order.push(position);
+        for dependent in &dependents[position] {
+            waiting_for[*dependent] -= 1;
+            if waiting_for[*dependent] == 0 {
+                ready.push(Reverse(*dependent));
+            }
+        }
+    }
+    order.extend((0..fragment_lineages.len()).filter(|position| waiting_for[*position] > 0));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::history::commit_files;
+    use crate::pipeline::lineage::tests::fragment_lineage;
+    use serde_json::json;
+
+    /// Creates a validator for a pipeline with a single transformation step, on a commit containing `a.csv` and `b.csv`.
+    fn setup() -> (HashMap<String, Dataset>, LineageValidator) {
+        let files = vec!["a.csv".to_owned(), "b.csv".to_owned()];
+        let (vc_dataset, commit) = commit_files(Dataset::new(), "master", "input", "", &files).unwrap();
+        let mut datasets = HashMap::new();
+        datasets.insert("dataset".to_owned(), vc_dataset);
+        let validator = LineageValidator {
+            input_dataset: "dataset".to_owned(),
+            input_commit: commit.hash,
+            steps: vec!["transform".to_owned()].into_iter().collect(),
+            fragments: HashMap::new(),
+        };
+        (datasets, validator)
+    }
+
+    fn with_input_files(mut lineage: FragmentLineage, files: &[&str]) -> FragmentLineage {
+        lineage.description.metadata.custom = Some(json!({ "input_files": files }));
+        lineage
+    }
+
+    fn nothing_stored(_: &str) -> Result<Option<FragmentLineage>, DaemonError> {
+        Ok(None)
+    }
+
+    #[test]
+    fn root_fragments_need_files_of_the_input_commit() {
+        let (datasets, mut validator) = setup();
+        let valid = fragment_lineage("f1", FRAGMENTER_STEP, &[], &["a.csv"]);
+        assert!(validator.validate(&datasets, &valid, nothing_stored).is_ok());
+        let invalid = fragment_lineage("f2", FRAGMENTER_STEP, &[], &["c.csv"]);
+        assert!(validator.validate(&datasets, &invalid, nothing_stored).is_err());
+    }
+
+    #[test]
+    fn every_fragment_needs_a_known_step() {
+        let (datasets, mut validator) = setup();
+        let root = fragment_lineage("f1", "unknown", &[], &["a.csv"]);
+        assert!(validator.validate(&datasets, &root, nothing_stored).is_err());
+
+        validator.record(&fragment_lineage("f1", FRAGMENTER_STEP, &[], &["a.csv"]));
+        let transformed = fragment_lineage("t1", "unknown", &["f1"], &["out.csv"]);
+        assert!(validator.validate(&datasets, &transformed, nothing_stored).is_err());
+        let transformed = fragment_lineage("t1", "transform", &["f1"], &["out.csv"]);
+        assert!(validator.validate(&datasets, &transformed, nothing_stored).is_ok());
+    }
+
+    #[test]
+    fn predecessors_are_looked_up_once() {
+        let (datasets, mut validator) = setup();
+        let mut lookups = Vec::new();
+        let mut stored = |fragment_id: &str| -> Result<Option<FragmentLineage>, DaemonError> {
+            lookups.push(fragment_id.to_owned());
+            Ok(match fragment_id {
+                "f1" => Some(fragment_lineage("f1", FRAGMENTER_STEP, &[], &["a.csv"])),
+                _ => None,
+            })
+        };
+        for fragment_id in &["t1", "t2"] {
+            let lineage = fragment_lineage(fragment_id, "transform", &["f1"], &["out.csv"]);
+            assert!(validator.validate(&datasets, &lineage, &mut stored).is_ok());
+        }
+        let lineage = fragment_lineage("t3", "transform", &["f2"], &["out.csv"]);
+        assert!(validator.validate(&datasets, &lineage, &mut stored).is_err());
+        assert_eq!(lookups, vec!["f1", "f2"]);
+    }
+
+    #[test]
+    fn input_files_have_to_be_outputs_of_predecessors() {
+        let (datasets, mut validator) = setup();
+        validator.record(&fragment_lineage("f1", FRAGMENTER_STEP, &[], &["a.csv"]));
+        let valid = with_input_files(fragment_lineage("t1", "transform", &["f1"], &["out.csv"]), &["a.csv"]);
+        assert!(validator.validate(&datasets, &valid, nothing_stored).is_ok());
+        let invalid = with_input_files(fragment_lineage("t2", "transform", &["f1"], &["out.csv"]), &["b.csv"]);
+        assert!(validator.validate(&datasets, &invalid, nothing_stored).is_err());
+    }
+
+    #[test]
+    fn unknown_input_commits_are_rejected() {
+        let (_, mut validator) = setup();
+        let root = fragment_lineage("f1", FRAGMENTER_STEP, &[], &["a.csv"]);
+        assert!(validator.validate(&HashMap::new(), &root, nothing_stored).is_err());
+    }
+
+    #[test]
+    fn batches_are_stored_after_their_predecessors() {
+        let lineages = vec![
+            fragment_lineage("t2", "transform", &["t1"], &["out.csv"]),
+            fragment_lineage("t1", "transform", &["f1"], &["mid.csv"]),
+            fragment_lineage("f1", FRAGMENTER_STEP, &[], &["a.csv"]),
+            fragment_lineage("f2", FRAGMENTER_STEP, &[], &["b.csv"]),
+            fragment_lineage("t3", "transform", &["stored"], &["other.csv"]),
+        ];
+        assert_eq!(storage_order(&lineages), vec![2, 1, 0, 3, 4]);
+    }
+
+    #[test]
+    fn cycles_are_stored_last() {
+        let lineages = vec![
+            fragment_lineage("a", "transform", &["b"], &[]),
+            fragment_lineage("b", "transform", &["a"], &[]),
+            fragment_lineage("c", "transform", &["c"], &[]),
+            fragment_lineage("f1", FRAGMENTER_STEP, &[], &["a.csv"]),
+        ];
+        assert_eq!(storage_order(&lineages), vec![3, 0, 1, 2]);
+    }
+}
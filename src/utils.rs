@@ -0,0 +1,203 @@
+//! Small helper functions which are shared between the different modules of the daemon.
+use crate::error::DaemonError;
+use actix_web::web::Bytes;
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::SinkExt;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Component, Path};
+use std::thread;
+
+/// Size of the chunks in which generated files are streamed.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of chunks which are buffered before the generating thread waits for the receiver.
+const STREAM_BUFFERED_CHUNKS: usize = 4;
+
+/// Lists all files in a directory and its subdirectories. The returned paths are relative to `root`.
+/// Symbolic links are skipped, so the listing never refers to files outside of `root`.
+pub fn list_files_recursively(root: &Path) -> Result<Vec<String>, std::io::Error> {
+    let mut files = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_symlink() {
+                warn!("Skipping symbolic link {}", path.display());
+            } else if file_type.is_dir() {
+                directories.push(path);
+            } else {
+                let relative_path = path.strip_prefix(root).unwrap();
+                files.push(relative_path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Computes the total size in bytes of the files in a directory and its subdirectories. A directory which does not exist has a size of zero.
+pub fn directory_size(root: &Path) -> Result<u64, std::io::Error> {
+    if !root.exists() {
+        return Ok(0);
+    }
+    list_files_recursively(root)?
+        .iter()
+        .map(|file| fs::metadata(root.join(file)).map(|metadata| metadata.len()))
+        .sum()
+}
+
+/// Links a file to a new location, or copies it when linking is not possible. Missing parent directories are created.
+pub fn link_or_copy(source: &Path, target: &Path) -> Result<(), std::io::Error> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::hard_link(source, target).is_err() {
+        fs::copy(source, target)?;
+    }
+    Ok(())
+}
+
+/// Computes the SHA-256 digest of a file, as a hexadecimal string.
+pub fn file_digest(path: &Path) -> Result<String, std::io::Error> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Whether a file name is a relative path which stays within the directory it is joined onto, such as `plots/loss.png`.
+pub fn is_contained_path(file_name: &str) -> bool {
+    !file_name.is_empty()
+        && Path::new(file_name)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Unpacks a tarball into the target directory. Tarballs containing links are refused, as these could refer to files outside of the tarball.
+pub fn unpack_without_links<R: io::Read>(reader: R, target: &Path) -> Result<(), DaemonError> {
+    fs::create_dir_all(target)?;
+    for entry in tar::Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            return Err(DaemonError::BadRequest(format!(
+                "Tarball contains link {}, which is not supported.",
+                entry.path()?.display()
+            )));
+        }
+        entry.unpack_in(target)?;
+    }
+    Ok(())
+}
+
+/// Writer which sends everything written to it over a channel, such that it can be streamed as the body of a response.
+struct ChannelWriter {
+    sender: mpsc::Sender<Result<Bytes, io::Error>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        block_on(self.sender.send(Ok(Bytes::copy_from_slice(buf))))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Receiver of the stream is gone."))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Streams the output of `write`, which runs on a separate thread, such that large files such as archives never have to be fully generated
+/// before they are sent. The thread blocks whenever the receiver falls behind. Errors are logged and end the stream with an error.
+pub fn stream_writer<F>(write: F) -> mpsc::Receiver<Result<Bytes, io::Error>>
+where
+    F: FnOnce(&mut dyn Write) -> Result<(), DaemonError> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFERED_CHUNKS);
+    thread::spawn(move || {
+        let mut error_sender = sender.clone();
+        let mut writer = io::BufWriter::with_capacity(STREAM_CHUNK_SIZE, ChannelWriter { sender });
+        if let Err(error) = write(&mut writer).and_then(|_| Ok(writer.flush()?)) {
+            error!("Generating streamed file failed: {}", error);
+            let _ = block_on(error_sender.send(Err(io::Error::new(io::ErrorKind::Other, error.to_string()))));
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contained_paths_stay_within_their_directory() {
+        assert!(is_contained_path("plots/loss.png"));
+        assert!(!is_contained_path(""));
+        assert!(!is_contained_path("../secret"));
+        assert!(!is_contained_path("plots/../../secret"));
+        assert!(!is_contained_path("/etc/passwd"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listing_skips_symbolic_links() {
+        let root = std::env::temp_dir().join(format!("daemon-utils-test-{}", std::process::id()));
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("nested/file.txt"), b"content").unwrap();
+        std::os::unix::fs::symlink("/etc", root.join("link")).unwrap();
+
+        let files = list_files_recursively(&root);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            files.unwrap(),
+            vec![Path::new("nested").join("file.txt").to_string_lossy()]
+        );
+    }
+
+    #[test]
+    fn tarballs_with_links_are_refused() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_path("passwd").unwrap();
+        header.set_link_name("/etc/passwd").unwrap();
+        header.set_cksum();
+        builder.append(&header, std::io::empty()).unwrap();
+        let tarball = builder.into_inner().unwrap();
+
+        let target = std::env::temp_dir().join(format!("daemon-import-test-{}", std::process::id()));
+        let result = unpack_without_links(tarball.as_slice(), &target);
+        let unpacked = target.join("passwd").exists();
+        fs::remove_dir_all(&target).unwrap();
+        assert!(matches!(result, Err(DaemonError::BadRequest(_))));
+        assert!(!unpacked);
+    }
+
+    #[test]
+    fn stream_writer_streams_everything_written() {
+        let content: Vec<u8> = (0..3 * STREAM_CHUNK_SIZE).map(|index| index as u8).collect();
+        let expected = content.clone();
+        let chunks: Vec<Result<Bytes, io::Error>> =
+            block_on(futures::StreamExt::collect(stream_writer(move |writer| {
+                Ok(writer.write_all(&content)?)
+            })));
+        let streamed: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.unwrap().to_vec()).collect();
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn stream_writer_ends_with_the_error() {
+        let chunks: Vec<Result<Bytes, io::Error>> = block_on(futures::StreamExt::collect(stream_writer(|_| {
+            Err(DaemonError::NotFound)
+        })));
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].is_err());
+    }
+}